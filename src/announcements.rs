@@ -0,0 +1,283 @@
+//! Messages sent to chat every so often, like a GitHub reminder.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{chat::ChatClient, config::Config, twitch_event_handler::TwitchEventHandler};
+
+fn config_default_offline_after() -> u64 {
+    30 * 60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnnouncementsConfig {
+    /// Seconds without chat messages before the stream counts as offline.
+    /// Twitch doesn't tell us when the stream goes offline, so chat activity is used instead.
+    #[serde(rename = "offline-after", default = "config_default_offline_after")]
+    offline_after: u64,
+}
+
+impl Default for AnnouncementsConfig {
+    fn default() -> Self {
+        Self {
+            offline_after: config_default_offline_after(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RawAnnouncement {
+    interval: u64,
+    #[serde(rename = "min-messages", default)]
+    min_messages: u64,
+    messages: Vec<String>,
+}
+
+/// Sends its messages in turn, one every interval.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawAnnouncement")]
+pub struct Announcement {
+    /// Seconds
+    interval: u64,
+    /// Chat messages needed since the last announcement.
+    #[serde(rename = "min-messages")]
+    min_messages: u64,
+    messages: Vec<String>,
+}
+
+impl TryFrom<RawAnnouncement> for Announcement {
+    type Error = String;
+
+    fn try_from(raw: RawAnnouncement) -> Result<Self, Self::Error> {
+        if raw.interval == 0 {
+            return Err("Announcement interval must be at least 1 second".to_owned());
+        }
+        if raw.messages.is_empty() {
+            return Err("Announcement needs at least one message".to_owned());
+        }
+        Ok(Self {
+            interval: raw.interval,
+            min_messages: raw.min_messages,
+            messages: raw.messages,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct AnnouncementState {
+    next: Instant,
+    messages_since: u64,
+    /// Index of the next message to send.
+    index: usize,
+}
+
+impl AnnouncementState {
+    fn new(announcement: &Announcement, now: Instant) -> Self {
+        Self {
+            next: now + Duration::from_secs(announcement.interval),
+            messages_since: 0,
+            index: 0,
+        }
+    }
+}
+
+pub struct Announcements {
+    bot_user_id: String,
+    config: AnnouncementsConfig,
+    announcements: BTreeMap<String, Announcement>,
+    states: HashMap<String, AnnouncementState>,
+    last_chat_message: Option<Instant>,
+}
+
+impl Announcements {
+    pub fn new(config: &Config) -> Self {
+        let mut announcements = Self {
+            bot_user_id: config.bot_user_id.clone(),
+            config: config.announcements.clone(),
+            announcements: BTreeMap::new(),
+            states: HashMap::new(),
+            last_chat_message: None,
+        };
+        announcements.set(config.data().announcements.clone(), Instant::now());
+        announcements
+    }
+
+    /// Keeps the progress of announcements that still exist.
+    fn set(&mut self, announcements: BTreeMap<String, Announcement>, now: Instant) {
+        self.states
+            .retain(|key, _| announcements.get(key) == self.announcements.get(key));
+        for (key, announcement) in announcements.iter() {
+            self.states
+                .entry(key.clone())
+                .or_insert_with(|| AnnouncementState::new(announcement, now));
+        }
+        self.announcements = announcements;
+    }
+
+    fn is_online(&self, now: Instant) -> bool {
+        self.last_chat_message.is_some_and(|last_chat_message| {
+            now.duration_since(last_chat_message) < Duration::from_secs(self.config.offline_after)
+        })
+    }
+
+    fn chat_message(&mut self, chatter_id: &str, now: Instant) {
+        if chatter_id == self.bot_user_id {
+            return;
+        }
+        // Timers restart when coming back online, instead of announcing straight away.
+        if !self.is_online(now) {
+            for (key, announcement) in self.announcements.iter() {
+                if let Some(state) = self.states.get_mut(key) {
+                    state.next = now + Duration::from_secs(announcement.interval);
+                }
+            }
+        }
+        self.last_chat_message = Some(now);
+        self.states
+            .values_mut()
+            .for_each(|state| state.messages_since += 1);
+    }
+
+    fn announce(&mut self, api: &mut dyn ChatClient, now: Instant) {
+        if !self.is_online(now) {
+            return;
+        }
+        for (key, announcement) in self.announcements.iter() {
+            let Some(state) = self.states.get_mut(key) else {
+                continue;
+            };
+            if now < state.next || state.messages_since < announcement.min_messages {
+                continue;
+            }
+            let message = &announcement.messages[state.index % announcement.messages.len()];
+            info!(announcement = %key, "Announcing");
+            let _ = api.send_message(message);
+            state.next = now + Duration::from_secs(announcement.interval);
+            state.messages_since = 0;
+            state.index = (state.index + 1) % announcement.messages.len();
+        }
+    }
+}
+
+impl TwitchEventHandler for Announcements {
+    fn name(&self) -> &'static str {
+        "announcements"
+    }
+
+    fn subscribed_events(&self) -> &[twitcheventsub::Subscription] {
+        &[twitcheventsub::Subscription::ChatMessage]
+    }
+
+    fn handle_event(
+        &mut self,
+        event: &twitcheventsub::Event,
+        _api: &mut dyn ChatClient,
+    ) -> Result<()> {
+        if let twitcheventsub::Event::ChatMessage(message) = event {
+            self.chat_message(&message.chatter.id, Instant::now());
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        self.announce(api, Instant::now());
+        Ok(())
+    }
+
+    /// Announcements waiting on chat messages, or paused while offline, are woken by chat.
+    fn next_update(&self) -> Option<Instant> {
+        if !self.is_online(Instant::now()) {
+            return None;
+        }
+        self.announcements
+            .iter()
+            .filter_map(|(key, announcement)| {
+                let state = self.states.get(key)?;
+                (state.messages_since >= announcement.min_messages).then_some(state.next)
+            })
+            .min()
+    }
+
+    fn reload(&mut self, config: &Config, _api: &mut dyn ChatClient) -> Result<()> {
+        self.bot_user_id = config.bot_user_id.clone();
+        self.config = config.announcements.clone();
+        self.set(config.data().announcements.clone(), Instant::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        time::{Duration, Instant},
+    };
+
+    use super::{Announcements, AnnouncementsConfig};
+    use crate::chat::{ChatAction, RecordingChatClient};
+
+    const ANNOUNCEMENTS: &str = r#"
+        socials:
+          interval: 60
+          min-messages: 2
+          messages: [ "github", "dotfiles" ]
+        "#;
+
+    #[test]
+    fn rotates_after_interval_and_messages() {
+        let mut announcements = Announcements {
+            bot_user_id: "bot".to_owned(),
+            config: AnnouncementsConfig { offline_after: 600 },
+            announcements: BTreeMap::new(),
+            states: HashMap::new(),
+            last_chat_message: None,
+        };
+        let start = Instant::now();
+        announcements.set(serde_yml::from_str(ANNOUNCEMENTS).unwrap(), start);
+        let at = |seconds| start + Duration::from_secs(seconds);
+        let announce = |announcements: &mut Announcements, seconds| {
+            let mut chat = RecordingChatClient::default();
+            announcements.announce(&mut chat, at(seconds));
+            chat.actions
+        };
+
+        // Offline until someone chats.
+        assert_eq!(announce(&mut announcements, 120), vec![]);
+        announcements.chat_message("viewer", at(130));
+        announcements.chat_message("bot", at(140));
+        assert_eq!(announce(&mut announcements, 200), vec![]);
+        announcements.chat_message("viewer", at(210));
+        assert_eq!(
+            announce(&mut announcements, 220),
+            vec![ChatAction::Message("github".to_owned())]
+        );
+
+        announcements.chat_message("viewer", at(230));
+        announcements.chat_message("viewer", at(240));
+        assert_eq!(announce(&mut announcements, 250), vec![]);
+        assert_eq!(
+            announce(&mut announcements, 280),
+            vec![ChatAction::Message("dotfiles".to_owned())]
+        );
+
+        // Chat went quiet for too long, so the stream is probably over.
+        announcements.chat_message("viewer", at(290));
+        announcements.chat_message("viewer", at(300));
+        assert_eq!(announce(&mut announcements, 1000), vec![]);
+    }
+
+    #[test]
+    fn rejects_empty_announcements() {
+        let err = serde_yml::from_str::<super::Announcement>("{ interval: 60, messages: [] }");
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("needs at least one message"));
+    }
+}
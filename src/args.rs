@@ -0,0 +1,502 @@
+//! Shared chat command parsing: prefix detection, tokenizing, & typed arguments.
+
+use std::{fmt, time::Duration};
+
+use url::Url;
+
+use crate::command::CommandArgsResult;
+
+/// Used until a config sets one.
+pub const DEFAULT_PREFIX: &str = "!";
+
+/// Splits on whitespace, "quoted strings" are kept together & may contain escaped \" quotes.
+/// Unterminated quotes run to the end of the text.
+pub fn tokenize(text: &str) -> Vec<String> {
+    tokenize_with_offsets(text)
+        .into_iter()
+        .map(|(_, token)| token)
+        .collect()
+}
+
+/// Like tokenize, along with the byte offset in the text each token starts at.
+fn tokenize_with_offsets(text: &str) -> Vec<(usize, String)> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    loop {
+        while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let Some(&(start, first)) = chars.peek() else {
+            break;
+        };
+        let mut token = String::new();
+        if first == '"' {
+            chars.next();
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' if chars.peek().is_some_and(|(_, c)| *c == '"') => {
+                        token.push(chars.next().unwrap().1)
+                    }
+                    '"' => break,
+                    c => token.push(c),
+                }
+            }
+        } else {
+            while let Some((_, c)) = chars.next_if(|(_, c)| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push((start, token));
+    }
+    tokens
+}
+
+/// Chat message split into the command name (lowercase & without prefix) & its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedCommand {
+    prefix: String,
+    name: String,
+    args: Vec<String>,
+    /// Message text after the prefix, free text arguments are taken from it as typed.
+    text: String,
+    /// Where each argument starts in text.
+    arg_offsets: Vec<usize>,
+}
+
+impl ParsedCommand {
+    /// Returns none if the message isn't a command.
+    pub fn parse(text: &str, prefix: &str) -> Option<Self> {
+        let text = text.trim_start().strip_prefix(prefix)?;
+        if text.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut tokens = tokenize_with_offsets(text).into_iter();
+        let name = tokens.next()?.1.to_lowercase();
+        if name.is_empty() {
+            return None;
+        }
+        let (arg_offsets, args) = tokens.unzip();
+        Some(Self {
+            prefix: prefix.to_owned(),
+            name,
+            args,
+            text: text.to_owned(),
+            arg_offsets,
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Arguments as typed, without any parsing.
+    pub fn raw_args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn args<'a>(&'a self, usage: &'a Usage) -> ArgReader<'a> {
+        ArgReader {
+            command: self,
+            usage,
+            index: 0,
+            arg: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamKind {
+    Required,
+    Optional,
+    /// Has to be typed as is, like the set in `!bugs set <value>`.
+    Literal,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Param {
+    name: &'static str,
+    kind: ParamKind,
+}
+
+impl Param {
+    pub const fn required(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Required,
+        }
+    }
+
+    pub const fn optional(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Optional,
+        }
+    }
+
+    pub const fn literal(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: ParamKind::Literal,
+        }
+    }
+}
+
+/// Parameters of a command, used to generate usage text.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage(pub &'static [Param]);
+
+impl Usage {
+    pub fn format(&self, prefix: &str, name: &str) -> String {
+        let mut usage = format!("{}{}", prefix, name);
+        for param in self.0 {
+            match param.kind {
+                ParamKind::Required => usage.push_str(&format!(" <{}>", param.name)),
+                ParamKind::Optional => usage.push_str(&format!(" [{}]", param.name)),
+                ParamKind::Literal => usage.push_str(&format!(" {}", param.name)),
+            }
+        }
+        usage
+    }
+}
+
+/// A command & its aliases, the first alias is the one shown in help.
+#[derive(Debug, Clone)]
+pub struct CommandHelp {
+    pub aliases: Vec<String>,
+    pub usage: Usage,
+}
+
+impl CommandHelp {
+    pub fn new<S: Into<String>>(aliases: impl IntoIterator<Item = S>, usage: Usage) -> Self {
+        Self {
+            aliases: aliases.into_iter().map(Into::into).collect(),
+            usage,
+        }
+    }
+
+    pub fn format(&self, prefix: &str) -> String {
+        let Some((name, aliases)) = self.aliases.split_first() else {
+            return String::new();
+        };
+        let mut help = self.usage.format(prefix, name);
+        if !aliases.is_empty() {
+            let aliases = aliases
+                .iter()
+                .map(|alias| format!("{}{}", prefix, alias))
+                .collect::<Vec<_>>();
+            help.push_str(&format!(" (also {})", aliases.join(", ")));
+        }
+        help
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgError(pub String);
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<Args> From<ArgError> for CommandArgsResult<Args> {
+    fn from(err: ArgError) -> Self {
+        CommandArgsResult::BadArguments(err.0)
+    }
+}
+
+/// Reads arguments in the order of the usage parameters.
+pub struct ArgReader<'a> {
+    command: &'a ParsedCommand,
+    usage: &'a Usage,
+    /// Next parameter.
+    index: usize,
+    /// Next argument, behind index once optional parameters are skipped.
+    arg: usize,
+}
+
+impl ArgReader<'_> {
+    fn usage(&self) -> String {
+        format!(
+            "Usage: {}",
+            self.usage.format(&self.command.prefix, &self.command.name)
+        )
+    }
+
+    pub fn optional<T: Arg>(&mut self) -> Result<Option<T>, ArgError> {
+        let param = self
+            .usage
+            .0
+            .get(self.index)
+            .expect("Read more arguments than the usage has parameters");
+        self.index += 1;
+        let Some(arg) = self.command.args.get(self.arg) else {
+            return Ok(None);
+        };
+        self.arg += 1;
+        T::parse(arg).map(Some).map_err(|err| {
+            ArgError(format!(
+                "Invalid <{}>: {}. {}",
+                param.name,
+                err,
+                self.usage()
+            ))
+        })
+    }
+
+    /// Like optional, but the next argument is left for the next parameter unless it matches.
+    pub fn optional_if<T: Arg>(
+        &mut self,
+        matches: impl FnOnce(&str) -> bool,
+    ) -> Result<Option<T>, ArgError> {
+        if self
+            .command
+            .args
+            .get(self.arg)
+            .is_some_and(|arg| matches(arg))
+        {
+            return self.optional();
+        }
+        self.index += 1;
+        Ok(None)
+    }
+
+    pub fn required<T: Arg>(&mut self) -> Result<T, ArgError> {
+        let name = self.usage.0.get(self.index).map(|param| param.name);
+        self.optional()?.ok_or_else(|| {
+            ArgError(format!(
+                "Missing <{}>. {}",
+                name.unwrap_or_default(),
+                self.usage()
+            ))
+        })
+    }
+
+    /// Errors unless the next argument is the literal parameter.
+    pub fn literal(&mut self) -> Result<(), ArgError> {
+        let name = self.usage.0.get(self.index).map(|param| param.name);
+        self.index += 1;
+        self.arg += 1;
+        match (self.command.args.get(self.arg - 1), name) {
+            (Some(arg), Some(name)) if arg.eq_ignore_ascii_case(name) => Ok(()),
+            _ => Err(ArgError(self.usage())),
+        }
+    }
+
+    /// Errors if there are arguments left after the ones read so far.
+    pub fn end(&self) -> Result<(), ArgError> {
+        if self.arg < self.command.args.len() {
+            return Err(ArgError(format!("Too many arguments. {}", self.usage())));
+        }
+        Ok(())
+    }
+
+    /// Everything after the arguments read so far exactly as typed, quotes & spacing included,
+    /// for when the last parameter is free text.
+    pub fn rest(&mut self) -> Result<String, ArgError> {
+        let rest = self
+            .command
+            .arg_offsets
+            .get(self.arg)
+            .map_or("", |&offset| self.command.text[offset..].trim_end())
+            .to_owned();
+        if rest.is_empty() {
+            let name = self.usage.0.get(self.index).map(|param| param.name);
+            return Err(ArgError(format!(
+                "Missing <{}>. {}",
+                name.unwrap_or_default(),
+                self.usage()
+            )));
+        }
+        self.index += 1;
+        self.arg = self.command.args.len();
+        Ok(rest)
+    }
+}
+
+/// Argument type that can be parsed from a single token.
+pub trait Arg: Sized {
+    /// Error should describe what was expected.
+    fn parse(arg: &str) -> Result<Self, String>;
+}
+
+impl Arg for String {
+    fn parse(arg: &str) -> Result<Self, String> {
+        Ok(arg.to_owned())
+    }
+}
+
+impl Arg for i64 {
+    fn parse(arg: &str) -> Result<Self, String> {
+        arg.parse()
+            .map_err(|_| "expected a whole number".to_owned())
+    }
+}
+
+impl Arg for Url {
+    fn parse(arg: &str) -> Result<Self, String> {
+        Url::parse(arg).map_err(|_| "expected a URL".to_owned())
+    }
+}
+
+/// Twitch username, with or without the @.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mention {
+    pub login: String,
+}
+
+impl Arg for Mention {
+    fn parse(arg: &str) -> Result<Self, String> {
+        let login = arg.strip_prefix("@").unwrap_or(arg);
+        if login.is_empty() || !login.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err("expected a username".to_owned());
+        }
+        Ok(Self {
+            login: login.to_lowercase(),
+        })
+    }
+}
+
+/// Like 90, 90s, 5m, or 1h30m. Plain numbers are seconds.
+impl Arg for Duration {
+    fn parse(arg: &str) -> Result<Self, String> {
+        const ERROR: &str = "expected a duration like 30s, 5m, or 1h30m";
+        if let Ok(seconds) = arg.parse() {
+            return Ok(Duration::from_secs(seconds));
+        }
+        let mut total: u64 = 0;
+        let mut number = String::new();
+        for c in arg.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            let unit = match c.to_ascii_lowercase() {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 60 * 60 * 24,
+                _ => return Err(ERROR.to_owned()),
+            };
+            let value: u64 = number.parse().map_err(|_| ERROR.to_owned())?;
+            total = value
+                .checked_mul(unit)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| "duration is too long".to_owned())?;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return Err(ERROR.to_owned());
+        }
+        Ok(Duration::from_secs(total))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{tokenize, Arg, Mention, Param, ParsedCommand, Usage};
+
+    #[test]
+    fn tokenizes_quotes_and_whitespace() {
+        assert_eq!(
+            tokenize("  !theme   \"tokyo night\" a\\\"b \"say \\\"hi\\\"\" \"unterminated"),
+            vec![
+                "!theme",
+                "tokyo night",
+                "a\\\"b",
+                "say \"hi\"",
+                "unterminated"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_commands() {
+        let command = ParsedCommand::parse("  !SR  https://youtu.be/x", "!").unwrap();
+        assert_eq!(command.name(), "sr");
+        assert_eq!(ParsedCommand::parse("sr", "!"), None);
+        assert_eq!(ParsedCommand::parse("! sr", "!"), None);
+
+        const USAGE: Usage = Usage(&[Param::required("count"), Param::optional("user")]);
+        let command = ParsedCommand::parse("!add 5 @Vulae_", "!").unwrap();
+        let mut args = command.args(&USAGE);
+        assert_eq!(args.required::<i64>(), Ok(5));
+        assert_eq!(
+            args.optional::<Mention>(),
+            Ok(Some(Mention {
+                login: "vulae_".to_owned()
+            }))
+        );
+
+        let command = ParsedCommand::parse("!add five", "!").unwrap();
+        assert_eq!(
+            command.args(&USAGE).required::<i64>().unwrap_err().0,
+            "Invalid <count>: expected a whole number. Usage: !add <count> [user]"
+        );
+        let command = ParsedCommand::parse("!add", "!").unwrap();
+        assert_eq!(
+            command.args(&USAGE).required::<i64>().unwrap_err().0,
+            "Missing <count>. Usage: !add <count> [user]"
+        );
+        let command = ParsedCommand::parse("!add 5 @Vulae_ again", "!").unwrap();
+        let mut args = command.args(&USAGE);
+        assert_eq!(args.required::<i64>(), Ok(5));
+        assert!(args.optional::<Mention>().unwrap().is_some());
+        assert_eq!(
+            args.end().unwrap_err().0,
+            "Too many arguments. Usage: !add <count> [user]"
+        );
+    }
+
+    #[test]
+    fn skips_unmatched_optionals() {
+        const USAGE: Usage = Usage(&[Param::optional("@user"), Param::required("quote")]);
+        let mentioned = |text| {
+            let command = ParsedCommand::parse(text, "!").unwrap();
+            let mut args = command.args(&USAGE);
+            let user = args
+                .optional_if::<Mention>(|arg| arg.starts_with('@'))
+                .unwrap()
+                .map(|mention| mention.login);
+            (user, args.rest())
+        };
+        assert_eq!(
+            mentioned("!addquote @Vulae_ it works"),
+            (Some("vulae_".to_owned()), Ok("it works".to_owned()))
+        );
+        assert_eq!(
+            mentioned("!addquote it works"),
+            (None, Ok("it works".to_owned()))
+        );
+        assert_eq!(
+            mentioned("!addquote @vulae_").1.unwrap_err().0,
+            "Missing <quote>. Usage: !addquote [@user] <quote>"
+        );
+    }
+
+    #[test]
+    fn keeps_free_text_as_typed() {
+        const USAGE: Usage = Usage(&[Param::required("name"), Param::required("response")]);
+        let command = ParsedCommand::parse("!addcmd  x  He said \"hi  there\"  ", "!").unwrap();
+        let mut args = command.args(&USAGE);
+        assert_eq!(args.required::<String>(), Ok("x".to_owned()));
+        assert_eq!(args.rest(), Ok("He said \"hi  there\"".to_owned()));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(Duration::parse("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(Duration::parse("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(Duration::parse("5M"), Ok(Duration::from_secs(300)));
+        assert!(Duration::parse("5x").is_err());
+        assert!(Duration::parse("m").is_err());
+        assert_eq!(
+            Duration::parse("9999999999999999999h"),
+            Err("duration is too long".to_owned())
+        );
+    }
+}
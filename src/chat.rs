@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use tracing::info;
+use twitcheventsub::{EventSubError, TwitchEventSubApi};
+
+/// Twitch rejects chat messages longer than this many characters.
+pub const MAX_MESSAGE_LENGTH: usize = 500;
+
+/// Cuts the message down to fit in one chat message, ending it with … if anything was cut.
+pub fn truncate(message: &str) -> String {
+    if message.chars().count() <= MAX_MESSAGE_LENGTH {
+        return message.to_owned();
+    }
+    let mut truncated = message
+        .chars()
+        .take(MAX_MESSAGE_LENGTH - 1)
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// Everything the bot can do to chat, so commands don't need a live Twitch connection.
+pub trait ChatClient {
+    fn send_message(&mut self, message: &str) -> Result<()>;
+    fn reply(&mut self, message: &str, reply_to_message_id: &str) -> Result<()>;
+    fn delete_message(&mut self, message_id: &str) -> Result<()>;
+    /// Duration is in seconds.
+    fn timeout_user(&mut self, user_id: &str, duration: u32, reason: &str) -> Result<()>;
+}
+
+fn event_sub_error(err: EventSubError) -> anyhow::Error {
+    anyhow!("Twitch API error: {:?}", err)
+}
+
+impl ChatClient for TwitchEventSubApi {
+    fn send_message(&mut self, message: &str) -> Result<()> {
+        self.send_chat_message(message).map_err(event_sub_error)?;
+        Ok(())
+    }
+
+    fn reply(&mut self, message: &str, reply_to_message_id: &str) -> Result<()> {
+        self.send_chat_message_with_reply(message, Some(reply_to_message_id.to_owned()))
+            .map_err(event_sub_error)?;
+        Ok(())
+    }
+
+    fn delete_message(&mut self, message_id: &str) -> Result<()> {
+        TwitchEventSubApi::delete_message(self, message_id).map_err(event_sub_error)?;
+        Ok(())
+    }
+
+    fn timeout_user(&mut self, user_id: &str, duration: u32, reason: &str) -> Result<()> {
+        TwitchEventSubApi::timeout_user(self, user_id, duration, reason);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatAction {
+    Message(String),
+    Reply {
+        message: String,
+        reply_to_message_id: String,
+    },
+    DeleteMessage(String),
+    TimeoutUser {
+        user_id: String,
+        duration: u32,
+        reason: String,
+    },
+}
+
+impl ChatAction {
+    pub fn perform(&self, api: &mut dyn ChatClient) -> Result<()> {
+        match self {
+            ChatAction::Message(message) => api.send_message(message),
+            ChatAction::Reply {
+                message,
+                reply_to_message_id,
+            } => api.reply(message, reply_to_message_id),
+            ChatAction::DeleteMessage(message_id) => api.delete_message(message_id),
+            ChatAction::TimeoutUser {
+                user_id,
+                duration,
+                reason,
+            } => api.timeout_user(user_id, *duration, reason),
+        }
+    }
+}
+
+/// Logs everything instead of sending it.
+#[derive(Debug, Default)]
+pub struct DryRunChatClient;
+
+impl ChatClient for DryRunChatClient {
+    fn send_message(&mut self, message: &str) -> Result<()> {
+        info!(message, "Dry run: message");
+        Ok(())
+    }
+
+    fn reply(&mut self, message: &str, reply_to_message_id: &str) -> Result<()> {
+        info!(message, reply_to_message_id, "Dry run: reply");
+        Ok(())
+    }
+
+    fn delete_message(&mut self, message_id: &str) -> Result<()> {
+        info!(message_id, "Dry run: delete message");
+        Ok(())
+    }
+
+    fn timeout_user(&mut self, user_id: &str, duration: u32, reason: &str) -> Result<()> {
+        info!(user_id, duration, reason, "Dry run: timeout user");
+        Ok(())
+    }
+}
+
+/// In-memory chat that records everything sent to it instead of sending it.
+#[allow(unused)]
+#[derive(Debug, Default)]
+pub struct RecordingChatClient {
+    pub actions: Vec<ChatAction>,
+}
+
+impl ChatClient for RecordingChatClient {
+    fn send_message(&mut self, message: &str) -> Result<()> {
+        self.actions.push(ChatAction::Message(message.to_owned()));
+        Ok(())
+    }
+
+    fn reply(&mut self, message: &str, reply_to_message_id: &str) -> Result<()> {
+        self.actions.push(ChatAction::Reply {
+            message: message.to_owned(),
+            reply_to_message_id: reply_to_message_id.to_owned(),
+        });
+        Ok(())
+    }
+
+    fn delete_message(&mut self, message_id: &str) -> Result<()> {
+        self.actions
+            .push(ChatAction::DeleteMessage(message_id.to_owned()));
+        Ok(())
+    }
+
+    fn timeout_user(&mut self, user_id: &str, duration: u32, reason: &str) -> Result<()> {
+        self.actions.push(ChatAction::TimeoutUser {
+            user_id: user_id.to_owned(),
+            duration,
+            reason: reason.to_owned(),
+        });
+        Ok(())
+    }
+}
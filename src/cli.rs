@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::Config;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Twitch bot for twitch.tv/vulae_")]
+pub struct Cli {
+    /// Config file, the data file is relative to this.
+    #[arg(long, default_value = "config.toml", global = true)]
+    pub config: PathBuf,
+    /// Print outgoing chat messages instead of sending them.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Disable the radio, even if configured.
+    #[arg(long, global = true)]
+    pub no_radio: bool,
+    /// Disable Neovim, even if configured.
+    #[arg(long, global = true)]
+    pub no_neovim: bool,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Parse the config & data files, reporting every error found.
+    CheckConfig,
+}
+
+impl Cli {
+    /// Command line flags override whatever the config says.
+    pub fn apply(&self, config: &mut Config) {
+        if self.no_radio {
+            config.radio = None;
+        }
+        if self.no_neovim {
+            config.neovim = None;
+        }
+    }
+
+    /// Overrides are applied before checking, so disabled commands can't cause collisions.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        Config::load_with(&self.config, |config| self.apply(config))
+    }
+}
+
+#[cfg(all(test, feature = "neovim"))]
+mod test {
+    use clap::Parser;
+
+    use super::Cli;
+
+    #[test]
+    fn overrides_apply_before_checking() -> anyhow::Result<()> {
+        let directory = std::env::temp_dir().join(format!(
+            "vulae-twitch-bot-cli-overrides-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory)?;
+        std::fs::write(
+            directory.join("config.toml"),
+            "bot-user-id = \"1\"\ndata-file = \"data.yaml\"\n[neovim]\n",
+        )?;
+        // Collides with neovim's !theme.
+        std::fs::write(
+            directory.join("data.yaml"),
+            "simple_reply_commands:\n  theme: { names: [ theme ], responds: hi }\n",
+        )?;
+        let config = directory.join("config.toml");
+        let config = config.to_str().unwrap();
+
+        let cli = Cli::parse_from(["vulae-twitch-bot", "--config", config]);
+        assert!(cli.load_config().is_err());
+        let cli = Cli::parse_from(["vulae-twitch-bot", "--config", config, "--no-neovim"]);
+        assert!(cli.load_config()?.neovim.is_none());
+        assert!(crate::config::Config::check(&cli.config, |config| cli.apply(config)).is_empty());
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
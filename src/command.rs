@@ -1,25 +1,256 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
-use twitcheventsub::{MessageData, TwitchEventSubApi};
+use serde::{Deserialize, Serialize};
+use twitcheventsub::MessageData;
+
+use crate::{
+    args::{CommandHelp, ParsedCommand},
+    chat::ChatClient,
+    config::Config,
+    permission::Permission,
+};
 
 #[derive(Debug)]
 pub enum CommandArgsResult<Args> {
     WrongCommand,
     UnsufficientPermissions,
+    /// Remaining time until the command can be used again.
+    Cooldown(Duration),
     BadArguments(String),
     Execute(Args),
 }
 
+impl<Args> CommandArgsResult<Args> {
+    pub fn map<T>(self, f: impl FnOnce(Args) -> T) -> CommandArgsResult<T> {
+        match self {
+            CommandArgsResult::WrongCommand => CommandArgsResult::WrongCommand,
+            CommandArgsResult::UnsufficientPermissions => {
+                CommandArgsResult::UnsufficientPermissions
+            }
+            CommandArgsResult::Cooldown(remaining) => CommandArgsResult::Cooldown(remaining),
+            CommandArgsResult::BadArguments(message) => CommandArgsResult::BadArguments(message),
+            CommandArgsResult::Execute(args) => CommandArgsResult::Execute(f(args)),
+        }
+    }
+
+    /// Lets parsing use `?` on [`CommandSettings::check`] & argument errors.
+    pub fn from_fn(f: impl FnOnce() -> Result<Args, CommandArgsResult<Args>>) -> Self {
+        match f() {
+            Ok(args) => CommandArgsResult::Execute(args),
+            Err(result) => result,
+        }
+    }
+}
+
+/// Keeps written data files free of settings that weren't changed.
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Per command settings shared by every command, aliases are without the prefix.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandSettings {
+    /// Replaces the default aliases of built-in commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub permission: Permission,
+    /// Overrides permission for specific aliases.
+    #[serde(
+        rename = "alias-permissions",
+        default,
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub alias_permissions: HashMap<String, Permission>,
+    /// Seconds between uses by anyone.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub cooldown: u64,
+    /// Seconds between uses by the same chatter.
+    #[serde(rename = "user-cooldown", default, skip_serializing_if = "is_default")]
+    pub user_cooldown: u64,
+    #[serde(skip)]
+    last_used: Option<Instant>,
+    #[serde(skip)]
+    user_last_used: HashMap<String, Instant>,
+}
+
+impl CommandSettings {
+    pub fn with_permission(permission: Permission) -> Self {
+        Self {
+            permission,
+            ..Default::default()
+        }
+    }
+
+    /// Configured aliases, or the defaults if none are configured.
+    pub fn aliases(&self, defaults: &[&str]) -> Vec<String> {
+        match &self.aliases {
+            Some(aliases) => aliases.iter().map(|alias| alias.to_lowercase()).collect(),
+            None => defaults.iter().map(|alias| alias.to_string()).collect(),
+        }
+    }
+
+    pub fn has_alias(&self, defaults: &[&str], alias: &str) -> bool {
+        self.aliases(defaults).iter().any(|a| a == alias)
+    }
+
+    /// Alias permission keys are matched ignoring case, like configured aliases.
+    pub fn required_permission(&self, alias: &str) -> Permission {
+        self.alias_permissions
+            .iter()
+            .find(|(key, _)| key.to_lowercase() == alias)
+            .map_or(self.permission, |(_, permission)| *permission)
+    }
+
+    pub fn permitted(&self, alias: &str, chat_message: &MessageData) -> bool {
+        Permission::of(chat_message) >= self.required_permission(alias)
+    }
+
+    /// Moderators & the broadcaster bypass cooldowns.
+    pub fn remaining_cooldown(&self, chat_message: &MessageData) -> Option<Duration> {
+        if Permission::of(chat_message) >= Permission::Moderator {
+            return None;
+        }
+        let remaining = |last_used: Option<&Instant>, cooldown: u64| {
+            Duration::from_secs(cooldown).checked_sub(last_used?.elapsed())
+        };
+        [
+            remaining(self.last_used.as_ref(), self.cooldown),
+            remaining(
+                self.user_last_used.get(&chat_message.chatter.id),
+                self.user_cooldown,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|remaining| !remaining.is_zero())
+        .max()
+    }
+
+    /// Should be called whenever the command is executed.
+    pub fn start_cooldown(&mut self, chat_message: &MessageData) {
+        let now = Instant::now();
+        self.last_used = Some(now);
+        let user_cooldown = Duration::from_secs(self.user_cooldown);
+        self.user_last_used
+            .retain(|_, last_used| now.duration_since(*last_used) < user_cooldown);
+        if !user_cooldown.is_zero() {
+            self.user_last_used
+                .insert(chat_message.chatter.id.clone(), now);
+        }
+    }
+
+    /// Keeps cooldowns going when settings are replaced by a reload.
+    pub fn inherit_cooldowns(&mut self, previous: &CommandSettings) {
+        self.last_used = previous.last_used;
+        self.user_last_used = previous.user_last_used.clone();
+    }
+
+    /// Checks both permission & cooldown, on failure returns what parse_args should return.
+    pub fn check<Args>(
+        &self,
+        alias: &str,
+        chat_message: &MessageData,
+    ) -> Result<(), CommandArgsResult<Args>> {
+        if !self.permitted(alias, chat_message) {
+            return Err(CommandArgsResult::UnsufficientPermissions);
+        }
+        if let Some(remaining) = self.remaining_cooldown(chat_message) {
+            return Err(CommandArgsResult::Cooldown(remaining));
+        }
+        Ok(())
+    }
+}
+
 pub trait Command<Args> {
-    /// Return none if chat_message isn't for this command.
-    fn parse_args(&self, chat_message: &MessageData) -> CommandArgsResult<Args>;
+    /// Used for logging & error reporting.
+    fn name(&self) -> &'static str;
+    /// Return [`CommandArgsResult::WrongCommand`] if the command isn't one of these.
+    fn parse_args(
+        &self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<Args>;
+    /// Every command this handles, used for help.
+    fn help(&self) -> Vec<CommandHelp>;
     fn execute(
         &mut self,
         args: Args,
         chat_message: &MessageData,
-        api: &mut TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()>;
     #[allow(unused)]
-    fn update(&mut self, api: &mut TwitchEventSubApi) -> Result<()> {
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        Ok(())
+    }
+    /// Should apply whatever settings can be changed without restarting.
+    #[allow(unused)]
+    fn reload(&mut self, config: &Config) -> Result<()> {
         Ok(())
     }
 }
+
+/// [`Command`] with its argument type erased, so different commands can be stored together.
+pub trait DynCommand {
+    fn name(&self) -> &'static str;
+    fn help(&self) -> Vec<CommandHelp>;
+    /// Parses, and if successful executes the command, returning the result of execution.
+    fn try_execute(
+        &mut self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+        api: &mut dyn ChatClient,
+    ) -> CommandArgsResult<Result<()>>;
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()>;
+    fn reload(&mut self, config: &Config) -> Result<()>;
+}
+
+struct BoxedCommand<C, Args> {
+    command: C,
+    _args: PhantomData<fn(Args)>,
+}
+
+impl<C: Command<Args>, Args> DynCommand for BoxedCommand<C, Args> {
+    fn name(&self) -> &'static str {
+        self.command.name()
+    }
+
+    fn help(&self) -> Vec<CommandHelp> {
+        self.command.help()
+    }
+
+    fn try_execute(
+        &mut self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+        api: &mut dyn ChatClient,
+    ) -> CommandArgsResult<Result<()>> {
+        self.command
+            .parse_args(command, chat_message)
+            .map(|args| self.command.execute(args, chat_message, api))
+    }
+
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        self.command.update(api)
+    }
+
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        self.command.reload(config)
+    }
+}
+
+pub fn boxed<C, Args>(command: C) -> Box<dyn DynCommand>
+where
+    C: Command<Args> + 'static,
+    Args: 'static,
+{
+    Box::new(BoxedCommand {
+        command,
+        _args: PhantomData,
+    })
+}
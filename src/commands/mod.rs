@@ -1,81 +1,182 @@
+#[cfg(feature = "neovim")]
 pub mod neovim;
+pub mod quotes;
+#[cfg(feature = "radio")]
 pub mod radio;
 pub mod simple_reply;
+pub mod usage;
 
-use anyhow::{Error, Result};
-use twitcheventsub::{MessageData, TwitchEventSubApi};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tracing::{debug, info};
+use twitcheventsub::MessageData;
 
 use crate::{
-    command::{Command, CommandArgsResult},
+    args::{ParsedCommand, DEFAULT_PREFIX},
+    chat::ChatClient,
+    command::{self, Command, CommandArgsResult, DynCommand},
     config::Config,
+    runtime::Waker,
+    supervisor::{Supervised, SupervisorConfig},
     twitch_event_handler::TwitchEventHandler,
 };
 
+/// Minimum time between cooldown replies to the same chatter.
+const COOLDOWN_REPLY_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct CommandRegistry {
-    radio: radio::Radio,
-    neovim: neovim::Neovim,
-    simple_reply_commands: simple_reply::SimpleReplyCommandHandler,
+    prefix: String,
+    commands: Vec<Supervised<dyn DynCommand>>,
+    supervisor: SupervisorConfig,
+    cooldown_replies: HashMap<String, Instant>,
+    help: usage::SharedHelp,
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_owned(),
+            commands: Vec::new(),
+            supervisor: SupervisorConfig::default(),
+            cooldown_replies: HashMap::new(),
+            help: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
 }
 
 impl CommandRegistry {
-    pub fn initialize(config: &Config) -> Result<Self> {
-        Ok(Self {
-            radio: radio::Radio::initialize(config.radio.clone())?,
-            neovim: neovim::Neovim::initialize()?,
-            simple_reply_commands: config.data().simple_reply_commands.clone(),
-        })
+    #[cfg_attr(not(feature = "radio"), allow(unused_variables))]
+    pub fn initialize(config: &Config, waker: &Waker) -> Result<Self> {
+        let mut registry = Self {
+            prefix: config.prefix.clone(),
+            supervisor: config.supervisor.clone(),
+            ..Default::default()
+        };
+        #[cfg(feature = "radio")]
+        if let Some(radio_config) = &config.radio {
+            registry.register(radio::Radio::initialize(
+                radio_config.clone(),
+                waker.clone(),
+            )?);
+        }
+        #[cfg(not(feature = "radio"))]
+        if config.radio.is_some() {
+            tracing::warn!("Radio is configured, but was not built with the radio feature");
+        }
+        #[cfg(feature = "neovim")]
+        if let Some(neovim_config) = &config.neovim {
+            registry.register(neovim::Neovim::initialize(neovim_config.clone())?);
+        }
+        #[cfg(not(feature = "neovim"))]
+        if config.neovim.is_some() {
+            tracing::warn!("Neovim is configured, but was not built with the neovim feature");
+        }
+        registry.register(quotes::Quotes::new(config));
+        registry.register(usage::UsageCommand::new(config, registry.help.clone()));
+        // Data commands come last, so built-ins take priority.
+        registry.register(config.data().simple_reply_commands.clone().managed(config));
+        Ok(registry)
+    }
+
+    pub fn register<C, Args>(&mut self, command: C) -> &mut Self
+    where
+        C: Command<Args> + 'static,
+        Args: 'static,
+    {
+        let command = command::boxed(command);
+        self.commands.push(Supervised::new(
+            command.name(),
+            command,
+            self.supervisor.clone(),
+        ));
+        self
     }
 
     pub fn try_execute(
         &mut self,
         chat_message: &MessageData,
-        api: &mut TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()> {
-        match self.radio.parse_args(chat_message) {
-            CommandArgsResult::BadArguments(message) => {
-                let _ = api
-                    .send_chat_message_with_reply(message, Some(chat_message.message_id.clone()));
+        let Some(parsed) = ParsedCommand::parse(&chat_message.message.text, &self.prefix) else {
+            return Ok(());
+        };
+        *self.help.borrow_mut() = self
+            .commands
+            .iter()
+            .flat_map(|command| command.inner().help())
+            .collect();
+
+        // Commands are tried in the order they were registered, only the first match runs.
+        for command in self.commands.iter_mut() {
+            if command.is_disabled() {
+                continue;
             }
-            CommandArgsResult::Execute(args) => {
-                if let Err(err) = self.radio.execute(args, chat_message, api) {
-                    println!("ERR: {:#?}", err);
+            // Execution errors are handled by the supervisor.
+            let Some(result) = command.run(api, |command, api| {
+                match command.try_execute(&parsed, chat_message, api) {
+                    CommandArgsResult::Execute(result) => result.map(CommandArgsResult::Execute),
+                    result => Ok(result.map(|_| ())),
                 }
-            }
-            _ => {}
-        }
-        match self.neovim.parse_args(chat_message) {
-            CommandArgsResult::BadArguments(message) => {
-                let _ = api
-                    .send_chat_message_with_reply(message, Some(chat_message.message_id.clone()));
-            }
-            CommandArgsResult::Execute(args) => {
-                if let Err(err) = self.neovim.execute(args, chat_message, api) {
-                    println!("ERR: {:#?}", err);
+            }) else {
+                break;
+            };
+            match result {
+                CommandArgsResult::WrongCommand => continue,
+                CommandArgsResult::UnsufficientPermissions => {
+                    debug!(
+                        command = command.name(),
+                        chatter.id = %chat_message.chatter.id,
+                        "Insufficient permissions"
+                    );
+                    let _ = api.reply(
+                        "You are not allowed to use this command.",
+                        &chat_message.message_id,
+                    );
                 }
+                CommandArgsResult::Cooldown(remaining) => {
+                    self.cooldown_replies
+                        .retain(|_, replied| replied.elapsed() < COOLDOWN_REPLY_INTERVAL);
+                    if !self.cooldown_replies.contains_key(&chat_message.chatter.id) {
+                        self.cooldown_replies
+                            .insert(chat_message.chatter.id.clone(), Instant::now());
+                        let _ = api.reply(
+                            &format!(
+                                "This command is on cooldown, try again in {}s.",
+                                remaining.as_secs_f32().ceil()
+                            ),
+                            &chat_message.message_id,
+                        );
+                    }
+                }
+                CommandArgsResult::BadArguments(message) => {
+                    let _ = api.reply(&message, &chat_message.message_id);
+                }
+                CommandArgsResult::Execute(()) => info!(
+                    command = command.name(),
+                    chatter.id = %chat_message.chatter.id,
+                    chatter.name = %chat_message.chatter.name,
+                    message = %chat_message.message.text,
+                    "Executed command"
+                ),
             }
-            _ => {}
-        }
-
-        if let CommandArgsResult::Execute(args) =
-            self.simple_reply_commands.parse_args(chat_message)
-        {
-            if let Err(err) = self.simple_reply_commands.execute(args, chat_message, api) {
-                println!("ERR: {:#?}", err);
-            }
+            break;
         }
 
         Ok(())
     }
-
-    pub fn update(&mut self, api: &mut TwitchEventSubApi) -> Result<()> {
-        self.radio.update(api)?;
-        self.neovim.update(api)?;
-
-        Ok(())
-    }
 }
 
 impl TwitchEventHandler for CommandRegistry {
+    fn name(&self) -> &'static str {
+        "commands"
+    }
+
     fn subscribed_events(&self) -> &[twitcheventsub::Subscription] {
         &[twitcheventsub::Subscription::ChatMessage]
     }
@@ -83,11 +184,26 @@ impl TwitchEventHandler for CommandRegistry {
     fn handle_event(
         &mut self,
         event: &twitcheventsub::Event,
-        api: &mut twitcheventsub::TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()> {
         match event {
             twitcheventsub::Event::ChatMessage(message) => self.try_execute(message, api),
             _ => Ok(()),
         }
     }
+
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        self.commands.iter_mut().for_each(|command| {
+            command.run(api, |command, api| command.update(api));
+        });
+        Ok(())
+    }
+
+    fn reload(&mut self, config: &Config, api: &mut dyn ChatClient) -> Result<()> {
+        self.prefix = config.prefix.clone();
+        self.commands.iter_mut().for_each(|command| {
+            command.run(api, |command, _| command.reload(config));
+        });
+        Ok(())
+    }
 }
@@ -4,32 +4,88 @@
 use std::{
     io::Write,
     net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
 };
 
 use anyhow::Result;
-use twitcheventsub::{MessageData, TwitchEventSubApi};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use twitcheventsub::MessageData;
 
-use crate::command::{Command, CommandArgsResult};
+use crate::{
+    args::{Arg, CommandHelp, Param, ParsedCommand, Usage},
+    chat::ChatClient,
+    command::{Command, CommandArgsResult, CommandSettings},
+    config::Config,
+};
+
+const THEME_ALIASES: &[&str] = &["theme", "settheme", "colorscheme"];
+const THEME_USAGE: Usage = Usage(&[Param::required("theme")]);
+
+/// Sent to the client as is, which reads it as one word.
+struct Theme(String);
+
+impl Arg for Theme {
+    fn parse(arg: &str) -> Result<Self, String> {
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            return Err("expected a theme name without spaces".to_owned());
+        }
+        Ok(Self(arg.to_owned()))
+    }
+}
+
+fn config_default_address() -> String {
+    "127.0.0.1:24694".to_owned()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeovimConfig {
+    #[serde(default = "config_default_address")]
+    address: String,
+    #[serde(default)]
+    theme: CommandSettings,
+}
+
+impl NeovimConfig {
+    /// Every command & its aliases.
+    pub fn commands(&self) -> Vec<(String, Vec<String>)> {
+        vec![("theme".to_owned(), self.theme.aliases(THEME_ALIASES))]
+    }
+}
 
 pub struct Neovim {
-    server: TcpListener,
-    connections: Vec<TcpStream>,
+    config: NeovimConfig,
+    /// Filled by the accept thread.
+    connections: Arc<Mutex<Vec<TcpStream>>>,
 }
 
 impl Neovim {
-    pub fn initialize() -> Result<Self> {
-        let server = TcpListener::bind("127.0.0.1:24694")?;
-        server.set_nonblocking(true)?;
+    pub fn initialize(config: NeovimConfig) -> Result<Self> {
+        let server = TcpListener::bind(&config.address)?;
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let accepted = connections.clone();
+        thread::spawn(move || {
+            for stream in server.incoming() {
+                match stream {
+                    Ok(connection) => {
+                        info!(peer = ?connection.peer_addr().ok(), "New connection");
+                        accepted.lock().unwrap().push(connection);
+                    }
+                    Err(err) => warn!(error = %err, "TCP connection error"),
+                }
+            }
+        });
         Ok(Self {
-            server,
-            connections: Vec::new(),
+            config,
+            connections,
         })
     }
 
     pub fn send(&mut self, message: &[u8]) {
-        self.connections.retain_mut(|connection| {
+        self.connections.lock().unwrap().retain_mut(|connection| {
             if let Err(err) = connection.write_all(message) {
-                println!("TCP connection send error: {:#?}", err);
+                warn!(error = %err, "TCP connection send error");
                 return err.kind() != std::io::ErrorKind::BrokenPipe;
             }
             true
@@ -43,44 +99,91 @@ pub enum NeovimArgs {
 }
 
 impl Command<NeovimArgs> for Neovim {
-    fn parse_args(&self, chat_message: &MessageData) -> CommandArgsResult<NeovimArgs> {
-        let mut split = chat_message.message.text.split(" ");
-        match split.next() {
-            Some("!theme") | Some("!settheme") | Some("!colorscheme") => {
-                let Some(theme) = split.next() else {
-                    return CommandArgsResult::BadArguments("Usage: !theme [theme]".to_owned());
-                };
-                CommandArgsResult::Execute(NeovimArgs::SetTheme(theme.to_owned()))
-            }
-            _ => CommandArgsResult::WrongCommand,
+    fn name(&self) -> &'static str {
+        "neovim"
+    }
+
+    fn parse_args(
+        &self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<NeovimArgs> {
+        if self.config.theme.has_alias(THEME_ALIASES, command.name()) {
+            CommandArgsResult::from_fn(|| {
+                self.config.theme.check(command.name(), chat_message)?;
+                let mut args = command.args(&THEME_USAGE);
+                let Theme(theme) = args.required()?;
+                args.end()?;
+                Ok(NeovimArgs::SetTheme(theme))
+            })
+        } else {
+            CommandArgsResult::WrongCommand
         }
     }
 
+    fn help(&self) -> Vec<CommandHelp> {
+        vec![CommandHelp::new(
+            self.config.theme.aliases(THEME_ALIASES),
+            THEME_USAGE,
+        )]
+    }
+
     #[allow(unused)]
     fn execute(
         &mut self,
         args: NeovimArgs,
         chat_message: &MessageData,
-        api: &mut TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()> {
         match args {
-            NeovimArgs::SetTheme(theme) => self.send(format!("set_theme {}", theme).as_bytes()),
+            NeovimArgs::SetTheme(theme) => {
+                self.config.theme.start_cooldown(chat_message);
+                self.send(format!("set_theme {}", theme).as_bytes());
+            }
         }
         Ok(())
     }
 
-    fn update(&mut self, _api: &mut TwitchEventSubApi) -> Result<()> {
-        if let Some(stream) = self.server.incoming().next() {
-            match stream {
-                Ok(connection) => {
-                    self.connections.push(connection);
-                    println!("New connection!");
-                }
-                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {}
-                Err(err) => println!("TCP connection error: {:#?}", err),
-            }
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        let Some(neovim_config) = &config.neovim else {
+            warn!("Neovim can only be disabled by restarting");
+            return Ok(());
+        };
+        let mut neovim_config = neovim_config.clone();
+        if neovim_config.address != self.config.address {
+            warn!("Address changes only apply after restarting");
         }
-
+        neovim_config.theme.inherit_cooldowns(&self.config.theme);
+        self.config = neovim_config;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Theme, THEME_USAGE};
+    use crate::args::ParsedCommand;
+
+    #[test]
+    fn rejects_themes_with_spaces() {
+        let theme = |text| {
+            let command = ParsedCommand::parse(text, "!").unwrap();
+            let mut args = command.args(&THEME_USAGE);
+            let Theme(theme) = args.required().map_err(|err| err.0)?;
+            args.end().map_err(|err| err.0)?;
+            Ok::<_, String>(theme)
+        };
+        assert_eq!(theme("!theme gruvbox"), Ok("gruvbox".to_owned()));
+        assert_eq!(
+            theme("!theme tokyo night"),
+            Err("Too many arguments. Usage: !theme <theme>".to_owned())
+        );
+        assert_eq!(
+            theme("!theme \"tokyo night\""),
+            Err(
+                "Invalid <theme>: expected a theme name without spaces. Usage: !theme <theme>"
+                    .to_owned()
+            )
+        );
+    }
+}
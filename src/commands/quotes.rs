@@ -0,0 +1,292 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use rand::prelude::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use twitcheventsub::MessageData;
+
+use crate::{
+    args::{CommandHelp, Mention, Param, ParsedCommand, Usage},
+    chat::{self, ChatClient},
+    command::{Command, CommandArgsResult, CommandSettings},
+    commands::simple_reply::Change,
+    config::Config,
+    permission::Permission,
+    persist,
+};
+
+const QUOTE_ALIASES: &[&str] = &["quote"];
+const ADD_ALIASES: &[&str] = &["addquote"];
+const DELETE_ALIASES: &[&str] = &["delquote"];
+const QUOTE_USAGE: Usage = Usage(&[Param::optional("number")]);
+const SEARCH_USAGE: Usage = Usage(&[Param::literal("search"), Param::required("text")]);
+const ADD_USAGE: Usage = Usage(&[Param::optional("@user"), Param::required("quote")]);
+const DELETE_USAGE: Usage = Usage(&[Param::required("number")]);
+
+fn config_default_manage() -> CommandSettings {
+    CommandSettings::with_permission(Permission::Moderator)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotesConfig {
+    #[serde(default)]
+    quote: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    add: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    delete: CommandSettings,
+}
+
+impl Default for QuotesConfig {
+    fn default() -> Self {
+        Self {
+            quote: CommandSettings::default(),
+            add: config_default_manage(),
+            delete: config_default_manage(),
+        }
+    }
+}
+
+impl QuotesConfig {
+    /// Every command & its aliases.
+    pub fn commands(&self) -> Vec<(String, Vec<String>)> {
+        vec![
+            ("quote".to_owned(), self.quote.aliases(QUOTE_ALIASES)),
+            ("add".to_owned(), self.add.aliases(ADD_ALIASES)),
+            ("delete".to_owned(), self.delete.aliases(DELETE_ALIASES)),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quote {
+    text: String,
+    /// Login of whoever said it.
+    #[serde(rename = "said-by")]
+    said_by: String,
+    added: Change,
+    /// Deleted quotes are kept, so their numbers aren't given to new quotes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted: Option<Change>,
+}
+
+impl Quote {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.is_some()
+    }
+}
+
+/// Quotes by number, numbers stay the same when other quotes are deleted.
+pub type QuoteBook = BTreeMap<u64, Quote>;
+
+fn existing(quotes: &QuoteBook) -> impl Iterator<Item = (&u64, &Quote)> {
+    quotes.iter().filter(|(_, quote)| !quote.is_deleted())
+}
+
+fn format_quote(number: u64, quote: &Quote) -> String {
+    format!(
+        "#{}: \"{}\" - {}, {}",
+        number,
+        quote.text,
+        quote.said_by,
+        quote.added.at.date_naive()
+    )
+}
+
+pub struct Quotes {
+    config: QuotesConfig,
+    quotes: QuoteBook,
+    data_file: PathBuf,
+}
+
+impl Quotes {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.quotes.clone(),
+            quotes: config.data().quotes.clone(),
+            data_file: config.data_file.clone(),
+        }
+    }
+
+    /// Writes one quote to the data file, then keeps it, leaving the rest of the file as is.
+    fn save(&mut self, number: u64, quote: Quote) -> Result<()> {
+        persist::write_yaml_entry(
+            &self.data_file,
+            "quotes",
+            &number.into(),
+            Some(&serde_yml::to_value(&quote)?),
+        )?;
+        self.quotes.insert(number, quote);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum QuotesArgs {
+    /// Random quote if none.
+    Quote(Option<i64>),
+    Search(String),
+    Add {
+        said_by: String,
+        text: String,
+    },
+    Delete(i64),
+}
+
+impl Command<QuotesArgs> for Quotes {
+    fn name(&self) -> &'static str {
+        "quotes"
+    }
+
+    fn parse_args(
+        &self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<QuotesArgs> {
+        let alias = command.name();
+        CommandArgsResult::from_fn(|| {
+            if self.config.quote.has_alias(QUOTE_ALIASES, alias) {
+                self.config.quote.check(alias, chat_message)?;
+                let is_search = command
+                    .raw_args()
+                    .first()
+                    .is_some_and(|arg| arg.eq_ignore_ascii_case("search"));
+                if is_search {
+                    let mut args = command.args(&SEARCH_USAGE);
+                    args.literal()?;
+                    return Ok(QuotesArgs::Search(args.rest()?));
+                }
+                Ok(QuotesArgs::Quote(command.args(&QUOTE_USAGE).optional()?))
+            } else if self.config.add.has_alias(ADD_ALIASES, alias) {
+                self.config.add.check(alias, chat_message)?;
+                let mut args = command.args(&ADD_USAGE);
+                let said_by = args
+                    .optional_if::<Mention>(|arg| arg.starts_with('@'))?
+                    .map_or_else(
+                        || chat_message.broadcaster.login.clone(),
+                        |mention| mention.login,
+                    );
+                Ok(QuotesArgs::Add {
+                    said_by,
+                    text: args.rest()?,
+                })
+            } else if self.config.delete.has_alias(DELETE_ALIASES, alias) {
+                self.config.delete.check(alias, chat_message)?;
+                Ok(QuotesArgs::Delete(command.args(&DELETE_USAGE).required()?))
+            } else {
+                Err(CommandArgsResult::WrongCommand)
+            }
+        })
+    }
+
+    fn help(&self) -> Vec<CommandHelp> {
+        vec![
+            CommandHelp::new(self.config.quote.aliases(QUOTE_ALIASES), QUOTE_USAGE),
+            CommandHelp::new(self.config.add.aliases(ADD_ALIASES), ADD_USAGE),
+            CommandHelp::new(self.config.delete.aliases(DELETE_ALIASES), DELETE_USAGE),
+        ]
+    }
+
+    fn execute(
+        &mut self,
+        args: QuotesArgs,
+        chat_message: &MessageData,
+        api: &mut dyn ChatClient,
+    ) -> Result<()> {
+        let find = |quotes: &QuoteBook, number: i64| {
+            u64::try_from(number)
+                .ok()
+                .filter(|number| quotes.get(number).is_some_and(|quote| !quote.is_deleted()))
+                .ok_or_else(|| format!("Quote #{} doesn't exist.", number))
+        };
+        let reply = match args {
+            QuotesArgs::Quote(number) => {
+                self.config.quote.start_cooldown(chat_message);
+                let number = match number {
+                    Some(number) => find(&self.quotes, number),
+                    None => existing(&self.quotes)
+                        .map(|(number, _)| *number)
+                        .choose(&mut rand::thread_rng())
+                        .ok_or_else(|| "There are no quotes yet.".to_owned()),
+                };
+                match number {
+                    Ok(number) => format_quote(number, &self.quotes[&number]),
+                    Err(err) => err,
+                }
+            }
+            QuotesArgs::Search(text) => {
+                self.config.quote.start_cooldown(chat_message);
+                let text = text.to_lowercase();
+                let found = existing(&self.quotes)
+                    .filter(|(_, quote)| quote.text.to_lowercase().contains(&text))
+                    .collect::<Vec<_>>();
+                match found.as_slice() {
+                    [] => format!("No quotes contain \"{}\".", text),
+                    [(number, quote)] => format_quote(**number, quote),
+                    found => format!(
+                        "{} quotes found: {}",
+                        found.len(),
+                        found
+                            .iter()
+                            .map(|(number, _)| format!("#{}", number))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                }
+            }
+            QuotesArgs::Add { said_by, text } => {
+                self.config.add.start_cooldown(chat_message);
+                let number = self.quotes.last_key_value().map_or(1, |(last, _)| last + 1);
+                self.save(
+                    number,
+                    Quote {
+                        text,
+                        said_by,
+                        added: Change::now(chat_message),
+                        deleted: None,
+                    },
+                )?;
+                info!(
+                    chatter.id = %chat_message.chatter.id,
+                    chatter.name = %chat_message.chatter.name,
+                    number,
+                    "Quote added"
+                );
+                format!("Added quote #{}.", number)
+            }
+            QuotesArgs::Delete(number) => {
+                self.config.delete.start_cooldown(chat_message);
+                match find(&self.quotes, number) {
+                    Ok(number) => {
+                        let mut quote = self.quotes[&number].clone();
+                        quote.deleted = Some(Change::now(chat_message));
+                        self.save(number, quote)?;
+                        info!(
+                            chatter.id = %chat_message.chatter.id,
+                            chatter.name = %chat_message.chatter.name,
+                            number,
+                            "Quote deleted"
+                        );
+                        format!("Deleted quote #{}.", number)
+                    }
+                    Err(err) => err,
+                }
+            }
+        };
+        // Quotes & searches can be as long as a chat message on their own.
+        let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
+        Ok(())
+    }
+
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        let mut quotes_config = config.quotes.clone();
+        quotes_config.quote.inherit_cooldowns(&self.config.quote);
+        quotes_config.add.inherit_cooldowns(&self.config.add);
+        quotes_config.delete.inherit_cooldowns(&self.config.delete);
+        self.config = quotes_config;
+        self.quotes = config.data().quotes.clone();
+        self.data_file = config.data_file.clone();
+        Ok(())
+    }
+}
@@ -1,28 +1,76 @@
-// TODO: Media controls metadata
 // TODO: Bigger playlist
-// TODO: Make song downloads non-blocking
+
+mod player;
 
 use std::{
     collections::VecDeque,
-    fs::File,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process,
     str::FromStr,
-    sync::mpsc::{self, Receiver},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::Duration,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use rand::prelude::SliceRandom;
 use serde::{Deserialize, Serialize};
-use twitcheventsub::{MessageData, TwitchEventSubApi};
+use tracing::{error, info, warn};
+use twitcheventsub::MessageData;
 use url::Url;
 
-use crate::command::{Command, CommandArgsResult};
+use self::player::{Player, Requester, Track, TrackId};
+use crate::{
+    args::{CommandHelp, Param, ParsedCommand, Usage},
+    chat::{self, ChatClient},
+    command::{Command, CommandArgsResult, CommandSettings},
+    config::Config,
+    permission::Permission,
+    persist,
+    runtime::Waker,
+    template,
+};
+
+const SONG_ALIASES: &[&str] = &["song", "currentsong"];
+const SKIP_ALIASES: &[&str] = &["skip", "skipsong"];
+const SONG_REQUEST_ALIASES: &[&str] = &["sr", "songrequest"];
+const SONG_REQUEST_USAGE: Usage = Usage(&[Param::required("url")]);
+const PREVIOUS_SONG_ALIASES: &[&str] = &["prevsong", "previoussong"];
+const HISTORY_ALIASES: &[&str] = &["history", "recentsongs"];
+/// Songs listed by the history command.
+const HISTORY_SHOWN: usize = 5;
+const QUEUE_ALIASES: &[&str] = &["queue", "songqueue"];
+/// Songs listed by the queue command.
+const QUEUE_SHOWN: usize = 5;
+const WRONG_SONG_ALIASES: &[&str] = &["wrongsong", "undo"];
+const REMOVE_ALIASES: &[&str] = &["remove", "removesong"];
+const REMOVE_USAGE: Usage = Usage(&[Param::required("number")]);
 
 fn config_default_playlist_blacklist_previous_songs_len() -> usize {
     5
 }
 
+fn config_default_moderator() -> CommandSettings {
+    CommandSettings::with_permission(Permission::Moderator)
+}
+
+fn config_default_history_length() -> usize {
+    100
+}
+
+fn config_default_request_queue_size() -> usize {
+    5
+}
+
+fn config_default_media_controls() -> bool {
+    true
+}
+
 fn config_default_audio_format() -> String {
     "vorbis".to_owned()
 }
@@ -50,29 +98,242 @@ pub struct RadioConfig {
         default = "config_default_audio_format_ext"
     )]
     audio_format_ext: String,
+    /// Saved history of played songs, defaults to history.json in playlist-path.
+    #[serde(
+        rename = "history-file",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    history_file: Option<PathBuf>,
+    /// Played songs kept in the history.
+    #[serde(rename = "history-length", default = "config_default_history_length")]
+    history_length: usize,
+    /// Song requests that can be downloading at once, more are turned away.
+    /// Changes only apply to the download thread's queue once restarted.
+    #[serde(
+        rename = "request-queue-size",
+        default = "config_default_request_queue_size"
+    )]
+    request_queue_size: usize,
+    /// Only has an effect if built with the media-controls feature.
+    #[serde(rename = "media-controls", default = "config_default_media_controls")]
+    media_controls: bool,
+    #[serde(default)]
+    song: CommandSettings,
+    #[serde(default = "config_default_moderator")]
+    skip: CommandSettings,
+    #[serde(rename = "song-request", default)]
+    song_request: CommandSettings,
+    #[serde(rename = "previous-song", default = "config_default_moderator")]
+    previous_song: CommandSettings,
+    #[serde(default)]
+    history: CommandSettings,
+    #[serde(default)]
+    queue: CommandSettings,
+    #[serde(rename = "wrong-song", default)]
+    wrong_song: CommandSettings,
+    #[serde(default = "config_default_moderator")]
+    remove: CommandSettings,
+}
+
+impl RadioConfig {
+    /// Every command & its aliases.
+    pub fn commands(&self) -> Vec<(String, Vec<String>)> {
+        vec![
+            ("song".to_owned(), self.song.aliases(SONG_ALIASES)),
+            ("skip".to_owned(), self.skip.aliases(SKIP_ALIASES)),
+            (
+                "song-request".to_owned(),
+                self.song_request.aliases(SONG_REQUEST_ALIASES),
+            ),
+            (
+                "previous-song".to_owned(),
+                self.previous_song.aliases(PREVIOUS_SONG_ALIASES),
+            ),
+            ("history".to_owned(), self.history.aliases(HISTORY_ALIASES)),
+            ("queue".to_owned(), self.queue.aliases(QUEUE_ALIASES)),
+            (
+                "wrong-song".to_owned(),
+                self.wrong_song.aliases(WRONG_SONG_ALIASES),
+            ),
+            ("remove".to_owned(), self.remove.aliases(REMOVE_ALIASES)),
+        ]
+    }
+
+    fn history_file(&self) -> PathBuf {
+        self.history_file
+            .clone()
+            .unwrap_or_else(|| self.playlist_path.join("history.json"))
+    }
+}
+
+#[derive(Debug)]
+enum RadioEvent {
+    #[cfg(feature = "media-controls")]
+    MediaControl(souvlaki::MediaControlEvent),
+    TrackFinished(TrackId),
+    Downloaded {
+        download: Download,
+        result: Result<PathBuf>,
+    },
+}
+
+/// Song request for the download thread, with everything needed to download it.
+#[derive(Debug, Clone)]
+struct Download {
+    song: RadioPlatformSong,
+    requested_by: Requester,
+    /// Message to reply to if the download fails.
+    message_id: String,
+    requested_path: PathBuf,
+    audio_format: String,
+    audio_format_ext: String,
+    /// Set once the request is taken back, shared with the download thread's copy.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Download {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn run(&self) -> Result<PathBuf> {
+        let output = self
+            .song
+            .apply_yt_dlp(
+                process::Command::new("yt-dlp")
+                    .stdout(process::Stdio::inherit())
+                    .arg("-x")
+                    .args(["--audio-format", &self.audio_format])
+                    .args(["-o", "%(extractor)s-%(id)s.%(ext)s"])
+                    .arg("--write-info-json")
+                    .args(["--paths", self.requested_path.to_str().unwrap()])
+                    .args([
+                        "--download-archive",
+                        &format!("{}/archive.txt", self.requested_path.to_str().unwrap()),
+                    ]),
+            )
+            .output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr
+                .lines()
+                .rev()
+                .find_map(|line| line.strip_prefix("ERROR: "))
+                .unwrap_or("yt-dlp failed");
+            return Err(anyhow!("{}", reason));
+        }
+        let mut song_path = self.requested_path.clone();
+        song_path.push(self.song.to_filename());
+        song_path.set_extension(&self.audio_format_ext);
+        if !song_path.exists() {
+            return Err(anyhow!("yt-dlp didn't create {}", song_path.display()));
+        }
+        Ok(song_path)
+    }
+}
+
+/// Song that finished playing, or was skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlayedSong {
+    path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    at: DateTime<Utc>,
+}
+
+impl PlayedSong {
+    fn song(&self) -> Option<RadioPlatformSong> {
+        RadioPlatformSong::from_filename(self.path.file_name()?.to_str()?)
+    }
+
+    /// Title if known, otherwise the URL.
+    fn describe(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            self.song().map_or_else(
+                || self.path.display().to_string(),
+                |song| song.to_url().to_string(),
+            )
+        })
+    }
+}
+
+fn load_history(path: &Path) -> Result<VecDeque<PlayedSong>> {
+    if !path.exists() {
+        return Ok(VecDeque::new());
+    }
+    let history = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&history).with_context(|| format!("Invalid {}", path.display()))
+}
+
+/// Downloads one song at a time, so the bot keeps running while yt-dlp does.
+/// At most queue_size downloads can be waiting.
+fn spawn_download_thread(
+    queue_size: usize,
+    events: Sender<RadioEvent>,
+    waker: Waker,
+) -> SyncSender<Download> {
+    let (downloads, rx) = mpsc::sync_channel::<Download>(queue_size);
+    thread::spawn(move || {
+        for download in rx {
+            if download.is_cancelled() {
+                info!(song = %download.song.to_url(), "Skipping cancelled song request");
+                continue;
+            }
+            info!(song = %download.song.to_url(), "Downloading");
+            let result = download.run();
+            if download.is_cancelled() {
+                info!(song = %download.song.to_url(), "Dropping cancelled song request");
+                continue;
+            }
+            if events
+                .send(RadioEvent::Downloaded { download, result })
+                .is_err()
+            {
+                return;
+            }
+            waker.wake();
+        }
+    });
+    downloads
 }
 
 #[allow(dead_code)]
 pub struct Radio {
     config: RadioConfig,
+    /// Kept alive for the sink, none if the sink isn't playing to an audio device.
     #[allow(unused)]
-    stream: rodio::OutputStream,
-    #[allow(unused)]
-    stream_handle: rodio::OutputStreamHandle,
-    sink: rodio::Sink,
-    played: Vec<RadioPlatformSong>,
-    queue: VecDeque<RadioPlatformSong>,
-    controls: souvlaki::MediaControls,
-    rx: Receiver<souvlaki::MediaControlEvent>,
+    stream: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+    player: Player,
+    /// Oldest first, saved to the history file.
+    history: VecDeque<PlayedSong>,
+    #[cfg(feature = "media-controls")]
+    controls: Option<souvlaki::MediaControls>,
+    /// Track & its state when media controls were last updated.
+    #[cfg(feature = "media-controls")]
+    controls_showing: Option<(TrackId, player::TrackState)>,
+    rx: Receiver<RadioEvent>,
+    /// Woken when commands change the current track, skipped tracks never finish to wake it.
+    waker: Waker,
+    downloads: SyncSender<Download>,
+    /// Song requests sent to the download thread that haven't finished yet, oldest first.
+    /// Downloads taken out of here are cancelled, & dropped once they finish.
+    pending_downloads: Vec<Download>,
 }
 
 impl Radio {
-    pub fn initialize(config: RadioConfig) -> Result<Self> {
+    pub fn initialize(config: RadioConfig, waker: Waker) -> Result<Self> {
         process::Command::new("yt-dlp")
             .stdout(process::Stdio::inherit())
             .arg("-x")
             .args(["--audio-format", &config.audio_format])
             .args(["-o", "%(extractor)s-%(id)s.%(ext)s"])
+            .arg("--write-info-json")
             .args(["--paths", config.playlist_path.to_str().unwrap()])
             .args([
                 "--download-archive",
@@ -84,43 +345,244 @@ impl Radio {
         let (stream, stream_handle) = rodio::OutputStream::try_default()?;
         let sink = rodio::Sink::try_new(&stream_handle)?;
         sink.set_volume(0.25);
+        let mut radio = Self::with_sink(config, sink, waker)?;
+        radio.stream = Some((stream, stream_handle));
+        Ok(radio)
+    }
+
+    /// Plays to the sink, without downloading the playlist first.
+    fn with_sink(config: RadioConfig, sink: rodio::Sink, waker: Waker) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let player = {
+            let tx = tx.clone();
+            let waker = waker.clone();
+            Player::new(sink, move |id| {
+                let _ = tx.send(RadioEvent::TrackFinished(id));
+                waker.wake();
+            })
+        };
+        #[cfg(feature = "media-controls")]
+        let controls = if config.media_controls {
+            Some(Self::attach_media_controls(tx.clone(), waker.clone())?)
+        } else {
+            None
+        };
+        let downloads = spawn_download_thread(config.request_queue_size, tx, waker.clone());
+        let history = load_history(&config.history_file()).unwrap_or_else(|err| {
+            warn!(error = %err, "Failed to load song history, starting a new one");
+            VecDeque::new()
+        });
+        Ok(Self {
+            config,
+            stream: None,
+            player,
+            history,
+            #[cfg(feature = "media-controls")]
+            controls,
+            #[cfg(feature = "media-controls")]
+            controls_showing: None,
+            rx,
+            waker,
+            downloads,
+            pending_downloads: Vec::new(),
+        })
+    }
+
+    #[cfg(feature = "media-controls")]
+    fn attach_media_controls(
+        tx: Sender<RadioEvent>,
+        waker: Waker,
+    ) -> Result<souvlaki::MediaControls> {
         let mut controls = souvlaki::MediaControls::new(souvlaki::PlatformConfig {
             display_name: "vulae-twitch-bot",
             dbus_name: "vulae-twitch-bot",
             hwnd: None,
         })?;
-        let (tx, rx) = mpsc::channel();
         controls.attach(move |event| {
-            tx.send(event).unwrap();
+            tx.send(RadioEvent::MediaControl(event)).unwrap();
+            waker.wake();
         })?;
         // Needs to have set metadata for events to start being recieved.
         controls.set_metadata(Default::default())?;
-        Ok(Self {
-            config,
-            stream,
-            stream_handle,
-            sink,
-            played: Vec::new(),
-            queue: VecDeque::new(),
-            controls,
-            rx,
+        Ok(controls)
+    }
+
+    /// Adds a finished or skipped track to the history.
+    fn played(&mut self, track: Track) {
+        self.history.push_back(PlayedSong {
+            path: track.path,
+            title: track.info.title,
+            at: Utc::now(),
+        });
+        while self.history.len() > self.config.history_length {
+            self.history.pop_front();
+        }
+        self.save_history();
+    }
+
+    fn save_history(&self) {
+        let path = self.config.history_file();
+        let saved = serde_json::to_string_pretty(&self.history)
+            .map_err(anyhow::Error::from)
+            .and_then(|history| persist::write_atomic(&path, &history));
+        if let Err(err) = saved {
+            warn!(path = %path.display(), error = %err, "Failed to save song history");
+        }
+    }
+
+    /// Returns what was skipped.
+    fn skip(&mut self) -> Option<String> {
+        let skipped = self.player.skip()?;
+        let described = skipped.describe();
+        self.played(skipped);
+        self.waker.wake();
+        Some(described)
+    }
+
+    /// Plays the last song in the history again, followed by the current song from the start.
+    /// Returns false if there's no history, the song stays in the history if it can't be loaded.
+    fn play_previous(&mut self) -> Result<bool> {
+        let Some(previous) = self.history.back() else {
+            return Ok(false);
+        };
+        info!(song = %previous.path.display(), "Previous");
+        let track = self.player.load(&previous.path, None)?;
+        self.history.pop_back();
+        self.player.play_now(track);
+        self.save_history();
+        self.waker.wake();
+        Ok(true)
+    }
+
+    /// Upcoming songs with who requested them & how long until they play.
+    /// The wait is left out after a song with an unknown duration.
+    fn describe_queue(&self) -> String {
+        let mut wait = match self.player.now_playing() {
+            Some(current) => current
+                .duration()
+                .map(|duration| duration.saturating_sub(self.player.position())),
+            None => Some(Duration::ZERO),
+        };
+        let mut queue = self
+            .player
+            .upcoming()
+            .take(QUEUE_SHOWN)
+            .enumerate()
+            .map(|(index, track)| {
+                let mut details = Vec::new();
+                if let Some(requester) = &track.requested_by {
+                    details.push(format!("requested by {}", requester.name));
+                }
+                if let Some(wait) = wait {
+                    details.push(format!("in {}", template::format_duration(wait)));
+                }
+                wait = wait
+                    .zip(track.duration())
+                    .map(|(wait, duration)| wait + duration);
+                if details.is_empty() {
+                    format!("{}. {}", index + 1, track.describe())
+                } else {
+                    format!(
+                        "{}. {} ({})",
+                        index + 1,
+                        track.describe(),
+                        details.join(", ")
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        let hidden = self.player.upcoming().len() - queue.len();
+        if hidden > 0 {
+            queue.push(format!("{} more", hidden));
+        }
+        if !self.pending_downloads.is_empty() {
+            queue.push(format!("{} downloading", self.pending_downloads.len()));
+        }
+        if queue.is_empty() {
+            "Nothing is queued.".to_owned()
+        } else {
+            format!("Up next: {}", queue.join(" | "))
+        }
+    }
+
+    /// Removes the chatter's most recent song request, skipping it if it's already playing.
+    /// Returns what was removed.
+    fn remove_latest_request(&mut self, chatter_id: &str) -> Option<String> {
+        let is_theirs = |track: &Track| {
+            track
+                .requested_by
+                .as_ref()
+                .is_some_and(|requester| requester.id == chatter_id)
+        };
+        if let Some(index) = self
+            .pending_downloads
+            .iter()
+            .rposition(|pending| pending.requested_by.id == chatter_id)
+        {
+            let cancelled = self.pending_downloads.remove(index);
+            cancelled.cancel();
+            return Some(cancelled.song.to_url().to_string());
+        }
+        let latest_upcoming = self.player.upcoming().rposition(is_theirs);
+        if let Some(index) = latest_upcoming {
+            return self.player.remove(index).map(|track| track.describe());
+        }
+        if self.player.now_playing().is_some_and(is_theirs) {
+            return self.skip();
+        }
+        None
+    }
+
+    /// Removes the song at a position in the queue, counting from 1 like the queue command.
+    /// Songs still downloading come after the upcoming ones. Returns what was removed.
+    fn remove_queued(&mut self, position: i64) -> Option<String> {
+        let index = usize::try_from(position).ok()?.checked_sub(1)?;
+        let upcoming = self.player.upcoming().len();
+        if index < upcoming {
+            return self.player.remove(index).map(|track| track.describe());
+        }
+        let index = index - upcoming;
+        (index < self.pending_downloads.len()).then(|| {
+            let cancelled = self.pending_downloads.remove(index);
+            cancelled.cancel();
+            cancelled.song.to_url().to_string()
         })
     }
 
-    fn load_next_song(&mut self, song_path: &PathBuf) -> Result<()> {
-        println!("Load: {:?}", song_path);
-        let source = rodio::Decoder::new(File::open(song_path)?)?;
-        self.sink.append(source);
-        self.sink.play();
-        let platform_song =
-            RadioPlatformSong::from_filename(song_path.file_name().unwrap().to_str().unwrap())
-                .unwrap();
-        self.played.push(platform_song.clone());
-        if self.played.len() > self.config.playlist_blacklist_previous_songs_len {
-            // Sorry
-            self.played.remove(0);
+    /// Shows the current track in desktop widgets & playerctl, if it or its state changed.
+    #[cfg(feature = "media-controls")]
+    fn update_media_controls(&mut self) -> Result<()> {
+        let Some(controls) = &mut self.controls else {
+            return Ok(());
+        };
+        let current = self.player.now_playing();
+        let showing = current.map(|current| (current.id(), current.state()));
+        if showing == self.controls_showing {
+            return Ok(());
+        }
+        let Some(current) = current else {
+            controls.set_metadata(Default::default())?;
+            controls.set_playback(souvlaki::MediaPlayback::Stopped)?;
+            self.controls_showing = showing;
+            return Ok(());
+        };
+        if self.controls_showing.map(|(id, _)| id) != Some(current.id()) {
+            let url = current.song.to_url();
+            let info = &current.info;
+            controls.set_metadata(souvlaki::MediaMetadata {
+                title: Some(info.title.as_deref().unwrap_or(url.as_str())),
+                album: None,
+                artist: info.artist.as_deref().or(info.uploader.as_deref()),
+                cover_url: info.thumbnail.as_deref(),
+                duration: current.duration(),
+            })?;
         }
-        self.queue.push_back(platform_song);
+        let progress = Some(souvlaki::MediaPosition(self.player.position()));
+        controls.set_playback(match current.state() {
+            player::TrackState::Paused => souvlaki::MediaPlayback::Paused { progress },
+            _ => souvlaki::MediaPlayback::Playing { progress },
+        })?;
+        self.controls_showing = showing;
         Ok(())
     }
 
@@ -142,27 +604,28 @@ impl Radio {
             .collect::<Vec<_>>();
 
         // Filter out just played songs
-        self.played[self
-            .played
-            .len()
-            .saturating_sub(self.config.playlist_blacklist_previous_songs_len)..]
+        self.history
             .iter()
+            .rev()
+            .take(self.config.playlist_blacklist_previous_songs_len)
+            .filter_map(PlayedSong::song)
             .for_each(|previous| {
                 song_paths.retain(|song_path| {
                     let Some(platform_song) = RadioPlatformSong::from_filename(
                         song_path.file_name().unwrap().to_str().unwrap(),
                     ) else {
-                        println!("This should never happen.");
+                        error!(song = %song_path.display(), "This should never happen.");
                         return false;
                     };
-                    *previous != platform_song
+                    previous != platform_song
                 });
             });
 
         // Random song
         let song_path = song_paths.choose(&mut rand::thread_rng()).unwrap();
 
-        self.load_next_song(song_path)?;
+        let track = self.player.load(song_path, None)?;
+        self.player.enqueue(track);
         Ok(())
     }
 }
@@ -224,177 +687,532 @@ pub enum RadioArgs {
     DisplayCurrentSong,
     SkipCurrentSong,
     SongRequest(RadioPlatformSong),
+    PreviousSong,
+    History,
+    Queue,
+    WrongSong,
+    /// Position in the queue, starting at 1.
+    Remove(i64),
 }
 
 impl Command<RadioArgs> for Radio {
-    fn parse_args(&self, chat_message: &MessageData) -> CommandArgsResult<RadioArgs> {
-        let mut split = chat_message.message.text.split(" ");
-        match split.next() {
-            Some("!currentsong") | Some("!song") => {
-                CommandArgsResult::Execute(RadioArgs::DisplayCurrentSong)
-            }
-            Some("!skipsong") | Some("!skip") => {
-                CommandArgsResult::Execute(RadioArgs::SkipCurrentSong)
+    fn name(&self) -> &'static str {
+        "radio"
+    }
+
+    fn parse_args(
+        &self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<RadioArgs> {
+        let alias = command.name();
+        if self.config.song.has_alias(SONG_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.song.check(alias, chat_message)?;
+                Ok(RadioArgs::DisplayCurrentSong)
+            })
+        } else if self.config.skip.has_alias(SKIP_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.skip.check(alias, chat_message)?;
+                Ok(RadioArgs::SkipCurrentSong)
+            })
+        } else if self
+            .config
+            .previous_song
+            .has_alias(PREVIOUS_SONG_ALIASES, alias)
+        {
+            CommandArgsResult::from_fn(|| {
+                self.config.previous_song.check(alias, chat_message)?;
+                Ok(RadioArgs::PreviousSong)
+            })
+        } else if self.config.history.has_alias(HISTORY_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.history.check(alias, chat_message)?;
+                Ok(RadioArgs::History)
+            })
+        } else if self.config.queue.has_alias(QUEUE_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.queue.check(alias, chat_message)?;
+                Ok(RadioArgs::Queue)
+            })
+        } else if self.config.wrong_song.has_alias(WRONG_SONG_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.wrong_song.check(alias, chat_message)?;
+                Ok(RadioArgs::WrongSong)
+            })
+        } else if self.config.remove.has_alias(REMOVE_ALIASES, alias) {
+            CommandArgsResult::from_fn(|| {
+                self.config.remove.check(alias, chat_message)?;
+                Ok(RadioArgs::Remove(command.args(&REMOVE_USAGE).required()?))
+            })
+        } else if self
+            .config
+            .song_request
+            .has_alias(SONG_REQUEST_ALIASES, alias)
+        {
+            if let Err(result) = self.config.song_request.check(alias, chat_message) {
+                return result;
             }
-            Some("!songrequest") | Some("!sr") => {
-                let Some(url_str) = split.next() else {
-                    return CommandArgsResult::BadArguments("Must include URL".to_owned());
-                };
-                let Ok(url) = Url::parse(url_str) else {
-                    return CommandArgsResult::BadArguments("Invalid URL".to_owned());
-                };
-                match url.domain() {
-                    Some("www.youtube.com") => {
-                        let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "v") else {
-                            return CommandArgsResult::BadArguments(
-                                "Could not extract YouTube video ID from URL".to_owned(),
-                            );
-                        };
-                        if let Ok(song) = RadioPlatformSong::new_youtube(&id) {
-                            CommandArgsResult::Execute(RadioArgs::SongRequest(song))
-                        } else {
-                            CommandArgsResult::BadArguments("Invalid YouTube ID".to_owned())
-                        }
+            let url: Url = match command.args(&SONG_REQUEST_USAGE).required() {
+                Ok(url) => url,
+                Err(err) => return err.into(),
+            };
+            match url.domain() {
+                Some("www.youtube.com") => {
+                    let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "v") else {
+                        return CommandArgsResult::BadArguments(
+                            "Could not extract YouTube video ID from URL".to_owned(),
+                        );
+                    };
+                    if let Ok(song) = RadioPlatformSong::new_youtube(&id) {
+                        CommandArgsResult::Execute(RadioArgs::SongRequest(song))
+                    } else {
+                        CommandArgsResult::BadArguments("Invalid YouTube ID".to_owned())
                     }
-                    Some("youtu.be") => {
-                        let Some(id) = url.path_segments().and_then(|mut segments| segments.next())
-                        else {
-                            return CommandArgsResult::BadArguments(
-                                "Could not extract YouTube video ID from URL".to_owned(),
-                            );
-                        };
-                        if let Ok(song) = RadioPlatformSong::new_youtube(id) {
-                            CommandArgsResult::Execute(RadioArgs::SongRequest(song))
-                        } else {
-                            CommandArgsResult::BadArguments("Invalid YouTube ID".to_owned())
-                        }
+                }
+                Some("youtu.be") => {
+                    let Some(id) = url.path_segments().and_then(|mut segments| segments.next())
+                    else {
+                        return CommandArgsResult::BadArguments(
+                            "Could not extract YouTube video ID from URL".to_owned(),
+                        );
+                    };
+                    if let Ok(song) = RadioPlatformSong::new_youtube(id) {
+                        CommandArgsResult::Execute(RadioArgs::SongRequest(song))
+                    } else {
+                        CommandArgsResult::BadArguments("Invalid YouTube ID".to_owned())
                     }
-                    _ => CommandArgsResult::BadArguments("Unsupported platform".to_owned()),
                 }
+                _ => CommandArgsResult::BadArguments("Unsupported platform".to_owned()),
             }
-            _ => CommandArgsResult::WrongCommand,
+        } else {
+            CommandArgsResult::WrongCommand
         }
     }
 
+    fn help(&self) -> Vec<CommandHelp> {
+        vec![
+            CommandHelp::new(self.config.song.aliases(SONG_ALIASES), Usage(&[])),
+            CommandHelp::new(self.config.skip.aliases(SKIP_ALIASES), Usage(&[])),
+            CommandHelp::new(
+                self.config.song_request.aliases(SONG_REQUEST_ALIASES),
+                SONG_REQUEST_USAGE,
+            ),
+            CommandHelp::new(
+                self.config.previous_song.aliases(PREVIOUS_SONG_ALIASES),
+                Usage(&[]),
+            ),
+            CommandHelp::new(self.config.history.aliases(HISTORY_ALIASES), Usage(&[])),
+            CommandHelp::new(self.config.queue.aliases(QUEUE_ALIASES), Usage(&[])),
+            CommandHelp::new(
+                self.config.wrong_song.aliases(WRONG_SONG_ALIASES),
+                Usage(&[]),
+            ),
+            CommandHelp::new(self.config.remove.aliases(REMOVE_ALIASES), REMOVE_USAGE),
+        ]
+    }
+
     fn execute(
         &mut self,
         args: RadioArgs,
         chat_message: &MessageData,
-        api: &mut TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()> {
         match args {
             RadioArgs::DisplayCurrentSong => {
-                if let Some(current_song) = self.queue.front() {
-                    let _ = api.send_chat_message_with_reply(
-                        current_song.to_url().to_string(),
-                        Some(chat_message.message_id.clone()),
-                    );
+                self.config.song.start_cooldown(chat_message);
+                if let Some(current) = self.player.now_playing() {
+                    let url = current.song.to_url();
+                    let mut reply = match &current.info.title {
+                        Some(title) => format!("{} - {}", title, url),
+                        None => url.to_string(),
+                    };
+                    if let Some(requester) = &current.requested_by {
+                        reply.push_str(&format!(" (requested by {})", requester.name));
+                    }
+                    let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
                 }
             }
             RadioArgs::SongRequest(platform_song) => {
-                println!(
-                    "{} ({}) requested: {}",
-                    chat_message.chatter.name,
-                    chat_message.chatter.id,
-                    platform_song.to_url()
+                let song = platform_song.to_url();
+                let download = Download {
+                    song: platform_song,
+                    requested_by: Requester::of(chat_message),
+                    message_id: chat_message.message_id.clone(),
+                    requested_path: self.config.requested_path.clone(),
+                    audio_format: self.config.audio_format.clone(),
+                    audio_format_ext: self.config.audio_format_ext.clone(),
+                    cancelled: Arc::default(),
+                };
+                // Cancelled requests the download thread hasn't reached yet still take up room.
+                let queued = self.pending_downloads.len() < self.config.request_queue_size
+                    && match self.downloads.try_send(download.clone()) {
+                        Ok(()) => true,
+                        Err(TrySendError::Full(_)) => false,
+                        Err(TrySendError::Disconnected(_)) => {
+                            return Err(anyhow!("Download thread stopped"))
+                        }
+                    };
+                if !queued {
+                    let _ = api.reply(
+                        "Too many songs are being requested right now, try again later.",
+                        &chat_message.message_id,
+                    );
+                    return Ok(());
+                }
+                self.config.song_request.start_cooldown(chat_message);
+                info!(
+                    chatter.id = %chat_message.chatter.id,
+                    chatter.name = %chat_message.chatter.name,
+                    %song,
+                    "Song requested"
+                );
+                self.pending_downloads.push(download);
+                let position = self.player.upcoming().len() + self.pending_downloads.len();
+                let _ = api.reply(
+                    &format!("Song queued at position {}.", position),
+                    &chat_message.message_id,
                 );
-
-                platform_song
-                    .apply_yt_dlp(
-                        process::Command::new("yt-dlp")
-                            .stdout(process::Stdio::inherit())
-                            .arg("-x")
-                            .args(["--audio-format", &self.config.audio_format])
-                            .args(["-o", "%(extractor)s-%(id)s.%(ext)s"])
-                            .args(["--paths", self.config.requested_path.to_str().unwrap()])
-                            .args([
-                                "--download-archive",
-                                &format!(
-                                    "{}/archive.txt",
-                                    self.config.requested_path.to_str().unwrap()
-                                ),
-                            ]),
-                    )
-                    .output()?;
-
-                let mut song_path = self.config.requested_path.clone();
-                song_path.push(platform_song.to_filename());
-                song_path.set_extension(&self.config.audio_format_ext);
-                self.load_next_song(&song_path)?;
             }
             RadioArgs::SkipCurrentSong => {
-                self.sink.skip_one();
+                self.config.skip.start_cooldown(chat_message);
+                self.skip();
+            }
+            RadioArgs::PreviousSong => {
+                self.config.previous_song.start_cooldown(chat_message);
+                match self.play_previous() {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let _ = api.reply("No song has played yet.", &chat_message.message_id);
+                    }
+                    Err(err) => {
+                        warn!(error = %err, "Failed to play previous song");
+                        let _ = api.reply(
+                            &chat::truncate(&format!("Couldn't play the previous song: {}", err)),
+                            &chat_message.message_id,
+                        );
+                    }
+                }
+            }
+            RadioArgs::History => {
+                self.config.history.start_cooldown(chat_message);
+                let recent = self
+                    .history
+                    .iter()
+                    .rev()
+                    .take(HISTORY_SHOWN)
+                    .map(PlayedSong::describe)
+                    .collect::<Vec<_>>();
+                let reply = if recent.is_empty() {
+                    "No song has played yet.".to_owned()
+                } else {
+                    format!("Recently played: {}", recent.join(" | "))
+                };
+                let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
+            }
+            RadioArgs::Queue => {
+                self.config.queue.start_cooldown(chat_message);
+                let _ = api.reply(
+                    &chat::truncate(&self.describe_queue()),
+                    &chat_message.message_id,
+                );
+            }
+            RadioArgs::WrongSong => {
+                self.config.wrong_song.start_cooldown(chat_message);
+                let reply = match self.remove_latest_request(&chat_message.chatter.id) {
+                    Some(removed) => {
+                        info!(
+                            chatter.id = %chat_message.chatter.id,
+                            chatter.name = %chat_message.chatter.name,
+                            song = %removed,
+                            "Song request taken back"
+                        );
+                        format!("Removed {}.", removed)
+                    }
+                    None => "You have no song requests queued.".to_owned(),
+                };
+                let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
+            }
+            RadioArgs::Remove(position) => {
+                self.config.remove.start_cooldown(chat_message);
+                let reply = match self.remove_queued(position) {
+                    Some(removed) => {
+                        info!(
+                            chatter.id = %chat_message.chatter.id,
+                            chatter.name = %chat_message.chatter.name,
+                            song = %removed,
+                            "Song removed from queue"
+                        );
+                        format!("Removed {}.", removed)
+                    }
+                    None => format!("There's no song at position {}.", position),
+                };
+                let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
             }
         }
         Ok(())
     }
 
-    fn update(&mut self, _api: &mut TwitchEventSubApi) -> Result<()> {
-        match self.rx.try_recv() {
-            Ok(event) => match event {
-                souvlaki::MediaControlEvent::Toggle => {
-                    if self.sink.is_paused() {
-                        self.sink.play();
-                    } else {
-                        self.sink.pause();
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        loop {
+            match self.rx.try_recv() {
+                #[cfg(feature = "media-controls")]
+                Ok(RadioEvent::MediaControl(event)) => match event {
+                    souvlaki::MediaControlEvent::Play => self.player.resume(),
+                    souvlaki::MediaControlEvent::Pause => self.player.pause(),
+                    souvlaki::MediaControlEvent::Toggle => {
+                        if self.player.is_paused() {
+                            self.player.resume();
+                        } else {
+                            self.player.pause();
+                        }
+                    }
+                    souvlaki::MediaControlEvent::Next => {
+                        self.skip();
+                    }
+                    souvlaki::MediaControlEvent::Previous => {
+                        if let Err(err) = self.play_previous() {
+                            warn!(error = %err, "Failed to play previous song");
+                        }
+                    }
+                    souvlaki::MediaControlEvent::SetPosition(souvlaki::MediaPosition(position)) => {
+                        if let Err(err) = self.player.seek(position) {
+                            warn!(error = %err, "Failed to seek");
+                        }
+                    }
+                    souvlaki::MediaControlEvent::SeekBy(direction, by) => {
+                        let position = self.player.position();
+                        let seeked = self.player.seek(match direction {
+                            souvlaki::SeekDirection::Forward => position + by,
+                            souvlaki::SeekDirection::Backward => position.saturating_sub(by),
+                        });
+                        if let Err(err) = seeked {
+                            warn!(error = %err, "Failed to seek");
+                        }
+                    }
+                    event => warn!(?event, "Unimplemented media control event"),
+                },
+                Ok(RadioEvent::TrackFinished(id)) => {
+                    if let Some(finished) = self.player.finished(id) {
+                        self.played(finished);
                     }
                 }
-                souvlaki::MediaControlEvent::Next => {
-                    self.sink.skip_one();
-                }
-                souvlaki::MediaControlEvent::Previous => {
-                    println!("Media control previous not implemented.")
+                Ok(RadioEvent::Downloaded { download, result }) => {
+                    let Some(index) = self
+                        .pending_downloads
+                        .iter()
+                        .position(|pending| pending.message_id == download.message_id)
+                    else {
+                        info!(song = %download.song.to_url(), "Dropping cancelled song request");
+                        continue;
+                    };
+                    self.pending_downloads.remove(index);
+                    match result {
+                        Ok(song_path) => {
+                            match self.player.load(&song_path, Some(download.requested_by)) {
+                                Ok(track) => self.player.enqueue(track),
+                                Err(err) => {
+                                    warn!(song = %song_path.display(), error = %err, "Downloaded song can't be played");
+                                    let _ = api.reply(
+                                        &chat::truncate(&format!(
+                                            "Couldn't play {}: {}",
+                                            download.song.to_url(),
+                                            err
+                                        )),
+                                        &download.message_id,
+                                    );
+                                }
+                            }
+                        }
+                        Err(err) => {
+                            warn!(song = %download.song.to_url(), error = %err, "Song download failed");
+                            let _ = api.reply(
+                                &chat::truncate(&format!(
+                                    "Couldn't download {}: {}",
+                                    download.song.to_url(),
+                                    err
+                                )),
+                                &download.message_id,
+                            );
+                        }
+                    }
                 }
-                event => println!("Unimplemented event {:#?}", event),
-            },
-            Err(mpsc::TryRecvError::Empty) => {}
-            Err(err) => return Err(err.into()),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(err) => return Err(err.into()),
+            }
         }
 
-        if self.sink.empty() {
-            self.queue.pop_front();
+        if self.player.is_idle() {
             self.load_random_next_song()?;
         }
+        #[cfg(feature = "media-controls")]
+        self.update_media_controls()?;
+        Ok(())
+    }
+
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        let Some(radio_config) = &config.radio else {
+            warn!("Radio can only be disabled by restarting");
+            return Ok(());
+        };
+        let mut radio_config = radio_config.clone();
+        if radio_config.playlist != self.config.playlist {
+            warn!("Playlist URL changes only apply after restarting");
+        }
+        radio_config.song.inherit_cooldowns(&self.config.song);
+        radio_config.skip.inherit_cooldowns(&self.config.skip);
+        radio_config
+            .song_request
+            .inherit_cooldowns(&self.config.song_request);
+        radio_config
+            .previous_song
+            .inherit_cooldowns(&self.config.previous_song);
+        radio_config.history.inherit_cooldowns(&self.config.history);
+        radio_config.queue.inherit_cooldowns(&self.config.queue);
+        radio_config
+            .wrong_song
+            .inherit_cooldowns(&self.config.wrong_song);
+        radio_config.remove.inherit_cooldowns(&self.config.remove);
+        self.config = radio_config;
         Ok(())
     }
 }
 
-//#[cfg(test)]
-//mod test {
-//    use std::time::Duration;
-//
-//    use anyhow::Result;
-//    use twitcheventsub::MessageData;
-//
-//    use crate::{
-//        command::{Command, CommandArgsResult},
-//        commands,
-//    };
-//
-//    fn create_test_message(content: &str) -> MessageData {
-//        serde_json::from_str(&format!("{{\"broadcaster_user_id\":\"1131985206\",\"broadcaster_user_name\":\"Vulae_\",\"broadcaster_user_login\":\"vulae_\",\"chatter_user_id\":\"1131985206\",\"chatter_user_name\":\"Vulae_\",\"chatter_user_login\":\"vulae_\",\"message_id\":\"52c08310-98e2-42f3-9300-2581b51d5dca\",\"message\":{{\"text\":\"{}\",\"fragments\":[{{\"type\":\"text\",\"text\":\"{}\",\"cheermote\":null,\"emote\":null,\"mention\":null}}]}},\"color\":\"\",\"badges\":[{{\"set_id\":\"broadcaster\",\"id\":\"1\",\"info\":\"\"}}],\"message_type\":\"text\",\"cheer\":null,\"reply\":null,\"channel_points_custom_reward_id\":null,\"channel_points_animation_id\":null}}", content, content)).unwrap()
-//    }
-//
-//    #[test]
-//    fn radio_command() -> Result<()> {
-//        let mut radio = commands::Radio::initialize()?;
-//
-//        let chat_message = create_test_message("!sr https://www.youtube.com/watch?v=dQw4w9WgXcQ");
-//        if let CommandArgsResult::Execute(args) = radio.parse_args(&chat_message) {
-//            radio.execute(args, &chat_message)?;
-//        }
-//
-//        std::thread::sleep(Duration::from_millis(1000));
-//        let chat_message = create_test_message("!skip");
-//        if let CommandArgsResult::Execute(args) = radio.parse_args(&chat_message) {
-//            radio.execute(args, &chat_message)?;
-//        }
-//
-//        std::thread::sleep(Duration::from_millis(100));
-//        radio.update()?;
-//
-//        std::thread::sleep(Duration::from_millis(1000));
-//
-//        Ok(())
-//    }
-//}
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc, time::Duration};
+
+    use anyhow::Result;
+
+    use super::{
+        player::{
+            test::{idle_sink, temp_directory, write_song},
+            Requester,
+        },
+        spawn_download_thread, Download, Radio, RadioConfig, RadioPlatformSong,
+    };
+    use crate::{
+        chat::ChatAction,
+        commands::CommandRegistry,
+        replay::{load_events, replay},
+        runtime::Waker,
+    };
+
+    fn requester(id: &str, name: &str) -> Requester {
+        Requester {
+            id: id.to_owned(),
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn skips_cancelled_downloads() {
+        let (events, rx) = mpsc::channel();
+        let (tx, _rx) = mpsc::channel();
+        let downloads = spawn_download_thread(1, events, Waker::new(tx));
+        let download = Download {
+            song: RadioPlatformSong::YouTube { id: "x".to_owned() },
+            requested_by: requester("48265913", "SomeViewer"),
+            message_id: "cancelled".to_owned(),
+            requested_path: std::env::temp_dir(),
+            audio_format: "wav".to_owned(),
+            audio_format_ext: "wav".to_owned(),
+            cancelled: Default::default(),
+        };
+        download.cancel();
+        downloads.send(download).unwrap();
+        drop(downloads);
+        // The thread stops without running yt-dlp, so nothing is reported.
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).err(),
+            Some(mpsc::RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn queue_and_removing_requests() -> Result<()> {
+        let directory = temp_directory("radio-queue")?;
+        let playlist = directory.join("playlist");
+        let requests = directory.join("requests");
+        std::fs::create_dir_all(&playlist)?;
+        std::fs::create_dir_all(&requests)?;
+        write_song(&playlist, "p", Some(("Playlist Song", 200.0)))?;
+        let config: RadioConfig = toml::from_str(&format!(
+            r#"
+            playlist = "https://www.youtube.com/playlist?list=test"
+            playlist-path = "{}"
+            requested-path = "{}"
+            audio-format-ext = "wav"
+            history-file = "{}"
+            media-controls = false
+            "#,
+            playlist.display(),
+            requests.display(),
+            directory.join("history.json").display(),
+        ))?;
+        let (tx, _rx) = mpsc::channel();
+        let mut radio = Radio::with_sink(config, idle_sink(), Waker::new(tx))?;
+
+        let broadcaster = requester("1131985206", "Vulae_");
+        let viewer = requester("48265913", "SomeViewer");
+        let moderator = requester("71092938", "ModPerson");
+        for (id, info, requested_by) in [
+            ("current", Some(("Current Song", 150.0)), &broadcaster),
+            ("a", Some(("Song A", 120.0)), &viewer),
+            ("b", None, &moderator),
+            ("c", Some(("Song C", 60.0)), &viewer),
+        ] {
+            let path = write_song(&requests, id, info)?;
+            let track = radio.player.load(&path, Some(requested_by.clone()))?;
+            radio.player.enqueue(track);
+        }
+        radio.pending_downloads.push(Download {
+            song: RadioPlatformSong::YouTube { id: "d".to_owned() },
+            requested_by: moderator.clone(),
+            message_id: "downloading".to_owned(),
+            requested_path: requests.clone(),
+            audio_format: "wav".to_owned(),
+            audio_format_ext: "wav".to_owned(),
+            cancelled: Default::default(),
+        });
+
+        let mut registry = CommandRegistry::default();
+        registry.register(radio);
+        let events = load_events(format!(
+            "{}/tests/replays/radio.jsonl",
+            env!("CARGO_MANIFEST_DIR")
+        ))?;
+        let replies = replay(&mut registry, events)?
+            .into_iter()
+            .map(|step| match step.actions.as_slice() {
+                [ChatAction::Reply { message, .. }] => message.clone(),
+                actions => panic!("Expected a reply: {:?}", actions),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            replies,
+            vec![
+                "Up next: 1. Song A (requested by SomeViewer, in 2m) \
+                | 2. https://youtube.com/watch?v=b (requested by ModPerson, in 4m) \
+                | 3. Song C (requested by SomeViewer) | 1 downloading",
+                "Removed Song C.",
+                "Removed Song A.",
+                "You have no song requests queued.",
+                "You are not allowed to use this command.",
+                // Songs still downloading are the most recent requests.
+                "Removed https://youtube.com/watch?v=d.",
+                "There's no song at position 2.",
+                "Removed https://youtube.com/watch?v=b.",
+                "Nothing is queued.",
+                // Requests that are playing get skipped, & a playlist song starts instead.
+                "Removed Current Song.",
+                "Playlist Song - https://youtube.com/watch?v=p",
+            ]
+        );
+        let history = std::fs::read_to_string(directory.join("history.json"))?;
+        assert!(history.contains("Current Song"));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,459 @@
+//! Owns the sink & every queued track, so what's playing is always what's reported.
+//! The sink only ever has the current track in it, upcoming tracks are kept here until played.
+// Some controls are only used by media controls.
+#![cfg_attr(not(feature = "media-controls"), allow(dead_code))]
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tracing::{info, warn};
+use twitcheventsub::MessageData;
+
+use super::RadioPlatformSong;
+
+/// Parts of the info JSON yt-dlp writes next to each song, everything is optional as songs
+/// downloaded before it was written don't have one.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SongInfo {
+    pub title: Option<String>,
+    /// Only set for music, uploader is used otherwise.
+    pub artist: Option<String>,
+    pub uploader: Option<String>,
+    /// Seconds
+    pub duration: Option<f64>,
+    pub thumbnail: Option<String>,
+}
+
+impl SongInfo {
+    fn load(song_path: &Path) -> Self {
+        let info_path = song_path.with_extension("info.json");
+        let info = std::fs::read_to_string(&info_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|info| Ok(serde_json::from_str(&info)?));
+        match info {
+            Ok(info) => info,
+            Err(err) => {
+                warn!(path = %info_path.display(), error = %err, "No song info");
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Chatter that requested a track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Requester {
+    pub id: String,
+    pub name: String,
+}
+
+impl Requester {
+    pub fn of(chat_message: &MessageData) -> Self {
+        Self {
+            id: chat_message.chatter.id.clone(),
+            name: chat_message.chatter.name.clone(),
+        }
+    }
+}
+
+/// Unique for every track loaded, so callbacks of tracks that were removed can be ignored.
+pub type TrackId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackState {
+    Queued,
+    Playing,
+    Paused,
+}
+
+pub struct Track {
+    id: TrackId,
+    pub song: RadioPlatformSong,
+    pub path: PathBuf,
+    pub info: SongInfo,
+    /// None for songs picked from the playlist.
+    pub requested_by: Option<Requester>,
+    /// Taken once the track starts playing.
+    source: Option<rodio::Decoder<File>>,
+    state: TrackState,
+}
+
+impl Track {
+    pub fn id(&self) -> TrackId {
+        self.id
+    }
+
+    pub fn state(&self) -> TrackState {
+        self.state
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.info.duration.map(Duration::from_secs_f64)
+    }
+
+    /// Title if known, otherwise the URL.
+    pub fn describe(&self) -> String {
+        self.info
+            .title
+            .clone()
+            .unwrap_or_else(|| self.song.to_url().to_string())
+    }
+}
+
+/// Called with the ID of a track once it played to the end.
+type FinishedCallback = Arc<dyn Fn(TrackId) + Send + Sync>;
+
+pub struct Player {
+    sink: rodio::Sink,
+    current: Option<Track>,
+    upcoming: VecDeque<Track>,
+    paused: bool,
+    next_id: TrackId,
+    finished: FinishedCallback,
+}
+
+impl Player {
+    pub fn new(sink: rodio::Sink, finished: impl Fn(TrackId) + Send + Sync + 'static) -> Self {
+        Self {
+            sink,
+            current: None,
+            upcoming: VecDeque::new(),
+            paused: false,
+            next_id: 0,
+            finished: Arc::new(finished),
+        }
+    }
+
+    /// Decodes the song, errors if the file is missing or isn't audio.
+    pub fn load(&mut self, path: &Path, requested_by: Option<Requester>) -> Result<Track> {
+        let song = path
+            .file_name()
+            .and_then(|file_name| RadioPlatformSong::from_filename(file_name.to_str()?))
+            .ok_or_else(|| anyhow!("{} isn't a song file", path.display()))?;
+        let source = rodio::Decoder::new(File::open(path)?)?;
+        self.next_id += 1;
+        Ok(Track {
+            id: self.next_id,
+            song,
+            path: path.to_owned(),
+            info: SongInfo::load(path),
+            requested_by,
+            source: Some(source),
+            state: TrackState::Queued,
+        })
+    }
+
+    pub fn now_playing(&self) -> Option<&Track> {
+        self.current.as_ref()
+    }
+
+    /// Position in the current track.
+    pub fn position(&self) -> Duration {
+        self.sink.get_pos()
+    }
+
+    pub fn upcoming(&self) -> impl DoubleEndedIterator<Item = &Track> + ExactSizeIterator {
+        self.upcoming.iter()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.current.is_none() && self.upcoming.is_empty()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Plays after everything else, or straight away if nothing is playing.
+    pub fn enqueue(&mut self, track: Track) {
+        self.upcoming.push_back(track);
+        if self.current.is_none() {
+            self.start_next();
+        }
+    }
+
+    /// Plays the track now, the current track is played again from the start after it.
+    /// The current track is dropped if it can't be loaded again.
+    pub fn play_now(&mut self, track: Track) {
+        if let Some(current) = self.stop_current() {
+            match self.load(&current.path, current.requested_by) {
+                Ok(replay) => self.upcoming.push_front(replay),
+                Err(err) => {
+                    warn!(song = %current.path.display(), error = %err, "Failed to reload song")
+                }
+            }
+        }
+        self.upcoming.push_front(track);
+        self.start_next();
+    }
+
+    /// Returns the skipped track.
+    pub fn skip(&mut self) -> Option<Track> {
+        let skipped = self.stop_current();
+        self.start_next();
+        skipped
+    }
+
+    /// Takes an upcoming track out of the queue, index 0 plays next.
+    pub fn remove(&mut self, index: usize) -> Option<Track> {
+        self.upcoming.remove(index)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.sink.pause();
+        if let Some(current) = &mut self.current {
+            current.state = TrackState::Paused;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.sink.play();
+        if let Some(current) = &mut self.current {
+            current.state = TrackState::Playing;
+        }
+    }
+
+    pub fn seek(&mut self, position: Duration) -> Result<()> {
+        if self.current.is_none() {
+            return Ok(());
+        }
+        self.sink
+            .try_seek(position)
+            .map_err(|err| anyhow!("Failed to seek: {}", err))
+    }
+
+    /// Called once the finished callback ran, returns the finished track if it's still current.
+    pub fn finished(&mut self, id: TrackId) -> Option<Track> {
+        if self.current.as_ref().map(Track::id) != Some(id) {
+            return None;
+        }
+        let finished = self.current.take();
+        self.start_next();
+        finished
+    }
+
+    fn stop_current(&mut self) -> Option<Track> {
+        let current = self.current.take()?;
+        // Its finished callback might still run, but it's ignored as the ID won't match.
+        self.sink.clear();
+        Some(current)
+    }
+
+    fn start_next(&mut self) {
+        let Some(mut track) = self.upcoming.pop_front() else {
+            return;
+        };
+        info!(song = %track.path.display(), "Playing");
+        if let Some(source) = track.source.take() {
+            self.sink.append(source);
+        }
+        let id = track.id;
+        let finished = self.finished.clone();
+        self.sink
+            .append(rodio::source::EmptyCallback::<f32>::new(Box::new(
+                move || finished(id),
+            )));
+        if self.paused {
+            self.sink.pause();
+            track.state = TrackState::Paused;
+        } else {
+            self.sink.play();
+            track.state = TrackState::Playing;
+        }
+        self.current = Some(track);
+    }
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use std::{
+        path::{Path, PathBuf},
+        thread,
+        time::Duration,
+    };
+
+    use anyhow::Result;
+
+    use super::{Player, Requester, TrackId, TrackState};
+
+    /// Sink that isn't connected to an audio device. Its output is pulled slowly in the
+    /// background, so clearing doesn't block & test songs take a few seconds to finish.
+    pub fn idle_sink() -> rodio::Sink {
+        let (sink, output) = rodio::Sink::new_idle();
+        thread::spawn(move || {
+            for (index, _) in output.enumerate() {
+                if index % 10 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        sink
+    }
+
+    pub fn temp_directory(name: &str) -> Result<PathBuf> {
+        let directory =
+            std::env::temp_dir().join(format!("vulae-twitch-bot-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        Ok(directory)
+    }
+
+    /// Silent WAV named like a downloaded YouTube video, with an info JSON if the title is set.
+    pub fn write_song(directory: &Path, id: &str, info: Option<(&str, f64)>) -> Result<PathBuf> {
+        const SAMPLES: u32 = 100_000;
+        const SAMPLE_RATE: u32 = 1000;
+        let mut wav = Vec::new();
+        wav.extend(b"RIFF");
+        wav.extend((36 + SAMPLES * 2).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        // PCM, mono
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(1u16.to_le_bytes());
+        wav.extend(SAMPLE_RATE.to_le_bytes());
+        wav.extend((SAMPLE_RATE * 2).to_le_bytes());
+        // 16 bit samples
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend((SAMPLES * 2).to_le_bytes());
+        wav.resize(wav.len() + SAMPLES as usize * 2, 0);
+
+        let path = directory.join(format!("youtube-{}.wav", id));
+        std::fs::write(&path, wav)?;
+        if let Some((title, duration)) = info {
+            std::fs::write(
+                path.with_extension("info.json"),
+                serde_json::json!({ "title": title, "duration": duration }).to_string(),
+            )?;
+        }
+        Ok(path)
+    }
+
+    fn upcoming_ids(player: &Player) -> Vec<TrackId> {
+        player.upcoming().map(|track| track.id()).collect()
+    }
+
+    fn player_with_songs(name: &str) -> Result<(PathBuf, Player, [PathBuf; 3])> {
+        let directory = temp_directory(name)?;
+        let songs = [
+            write_song(&directory, "a", Some(("Song A", 120.0)))?,
+            write_song(&directory, "b", Some(("Song B", 60.0)))?,
+            write_song(&directory, "c", None)?,
+        ];
+        Ok((directory, Player::new(idle_sink(), |_| {}), songs))
+    }
+
+    #[test]
+    fn plays_tracks_in_order() -> Result<()> {
+        let (directory, mut player, [a, b, c]) = player_with_songs("player-order")?;
+        let a = player.load(&a, None)?;
+        let b = player.load(&b, None)?;
+        let c = player.load(&c, None)?;
+        let (a_id, b_id, c_id) = (a.id(), b.id(), c.id());
+
+        assert!(player.is_idle());
+        player.enqueue(a);
+        player.enqueue(b);
+        player.enqueue(c);
+        let current = player.now_playing().unwrap();
+        assert_eq!(current.id(), a_id);
+        assert_eq!(current.state(), TrackState::Playing);
+        assert_eq!(upcoming_ids(&player), vec![b_id, c_id]);
+
+        // Only the current track can finish.
+        assert!(player.finished(b_id).is_none());
+        assert_eq!(player.skip().map(|track| track.id()), Some(a_id));
+        assert_eq!(player.now_playing().map(|track| track.id()), Some(b_id));
+        // Skipped tracks might still call back, which is ignored.
+        assert!(player.finished(a_id).is_none());
+        assert_eq!(player.finished(b_id).map(|track| track.id()), Some(b_id));
+        assert_eq!(player.now_playing().map(|track| track.id()), Some(c_id));
+        assert!(upcoming_ids(&player).is_empty());
+        assert!(player.finished(c_id).is_some());
+        assert!(player.is_idle());
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn plays_now_before_current() -> Result<()> {
+        let (directory, mut player, [a, b, c]) = player_with_songs("player-play-now")?;
+        let requester = Requester {
+            id: "1".to_owned(),
+            name: "Viewer".to_owned(),
+        };
+        let a = player.load(&a, Some(requester.clone()))?;
+        let b = player.load(&b, None)?;
+        let c = player.load(&c, None)?;
+        let (a_id, b_id, c_id) = (a.id(), b.id(), c.id());
+        player.enqueue(a);
+        player.enqueue(b);
+
+        player.play_now(c);
+        assert_eq!(player.now_playing().map(|track| track.id()), Some(c_id));
+        // The interrupted track is loaded again to play from the start.
+        let upcoming = player.upcoming().collect::<Vec<_>>();
+        assert_eq!(upcoming.len(), 2);
+        assert_ne!(upcoming[0].id(), a_id);
+        assert_eq!(upcoming[0].describe(), "Song A");
+        assert_eq!(upcoming[0].requested_by, Some(requester));
+        assert_eq!(upcoming[1].id(), b_id);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn removes_upcoming_tracks() -> Result<()> {
+        let (directory, mut player, [a, b, c]) = player_with_songs("player-remove")?;
+        for song in [&a, &b, &c] {
+            let track = player.load(song, None)?;
+            player.enqueue(track);
+        }
+
+        assert!(player.remove(2).is_none());
+        assert_eq!(
+            player.remove(1).map(|track| track.describe()),
+            Some("https://youtube.com/watch?v=c".to_owned())
+        );
+        assert_eq!(
+            player.remove(0).map(|track| track.describe()),
+            Some("Song B".to_owned())
+        );
+        assert_eq!(player.now_playing().unwrap().describe(), "Song A");
+        assert!(upcoming_ids(&player).is_empty());
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn stays_paused_between_tracks() -> Result<()> {
+        let (directory, mut player, [a, b, _]) = player_with_songs("player-pause")?;
+        for song in [&a, &b] {
+            let track = player.load(song, None)?;
+            player.enqueue(track);
+        }
+
+        player.pause();
+        assert!(player.is_paused());
+        assert_eq!(player.now_playing().unwrap().state(), TrackState::Paused);
+        player.skip();
+        assert_eq!(player.now_playing().unwrap().state(), TrackState::Paused);
+        player.resume();
+        assert_eq!(player.now_playing().unwrap().state(), TrackState::Playing);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
@@ -1,49 +1,825 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
 
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use rand::prelude::SliceRandom;
 use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 use twitcheventsub::MessageData;
 
-use crate::command::{Command, CommandArgsResult};
+use crate::{
+    args::{ArgError, ArgReader, CommandHelp, Param, ParsedCommand, Usage},
+    chat::ChatClient,
+    command::{Command, CommandArgsResult, CommandSettings},
+    config::Config,
+    permission::Permission,
+    persist, runtime,
+    template::{Template, TemplateContext},
+};
+
+const ADD_ALIASES: &[&str] = &["addcmd"];
+const EDIT_ALIASES: &[&str] = &["editcmd"];
+const DELETE_ALIASES: &[&str] = &["delcmd"];
+const ALIAS_ALIASES: &[&str] = &["alias"];
+const INFO_ALIASES: &[&str] = &["cmdinfo"];
+const CHANGE_USAGE: Usage = Usage(&[Param::required("command"), Param::required("response")]);
+const ALIAS_USAGE: Usage = Usage(&[Param::required("command"), Param::required("alias")]);
+const COMMAND_USAGE: Usage = Usage(&[Param::required("command")]);
+const COUNTER_SET_USAGE: Usage = Usage(&[Param::literal("set"), Param::required("value")]);
+
+fn config_default_manage() -> CommandSettings {
+    CommandSettings::with_permission(Permission::Moderator)
+}
+
+fn config_default_counter_permission() -> Permission {
+    Permission::Moderator
+}
+
+/// Chat commands that change the data file's simple replies.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomCommandsConfig {
+    #[serde(default = "config_default_manage")]
+    add: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    edit: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    delete: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    alias: CommandSettings,
+    #[serde(default = "config_default_manage")]
+    info: CommandSettings,
+}
+
+impl Default for CustomCommandsConfig {
+    fn default() -> Self {
+        Self {
+            add: config_default_manage(),
+            edit: config_default_manage(),
+            delete: config_default_manage(),
+            alias: config_default_manage(),
+            info: config_default_manage(),
+        }
+    }
+}
+
+impl CustomCommandsConfig {
+    /// Every command & its aliases.
+    pub fn commands(&self) -> Vec<(String, Vec<String>)> {
+        vec![
+            ("add".to_owned(), self.add.aliases(ADD_ALIASES)),
+            ("edit".to_owned(), self.edit.aliases(EDIT_ALIASES)),
+            ("delete".to_owned(), self.delete.aliases(DELETE_ALIASES)),
+            ("alias".to_owned(), self.alias.aliases(ALIAS_ALIASES)),
+            ("info".to_owned(), self.info.aliases(INFO_ALIASES)),
+        ]
+    }
+}
+
+/// Who changed something from chat, & when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    /// Login of the chatter.
+    pub by: String,
+    pub at: DateTime<Utc>,
+}
+
+impl Change {
+    pub fn now(chat_message: &MessageData) -> Self {
+        Self {
+            by: chat_message.chatter.login.clone(),
+            at: Utc::now(),
+        }
+    }
+}
+
+/// Makes a simple reply a counter, changed with `!name+`, `!name-`, & `!name set <value>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Counter {
+    #[serde(default)]
+    value: i64,
+    /// Needed to change the value.
+    #[serde(default = "config_default_counter_permission")]
+    permission: Permission,
+    /// Text file that always has the value, for showing on stream with OBS.
+    /// Relative to the data file.
+    #[serde(rename = "obs-file", default, skip_serializing_if = "Option::is_none")]
+    obs_file: Option<PathBuf>,
+}
+
+fn config_default_weight() -> u32 {
+    1
+}
+
+fn config_default_avoid_repeats() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawResponse {
+    Plain(String),
+    Weighted {
+        response: String,
+        #[serde(default = "config_default_weight")]
+        weight: u32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawResponses {
+    One(String),
+    Many(Vec<RawResponse>),
+}
+
+/// One response, or a list to pick from at random, optionally weighted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawResponses", into = "RawResponses")]
+pub struct Responses {
+    responses: Vec<(Template, u32)>,
+    last: Option<usize>,
+}
+
+impl TryFrom<RawResponses> for Responses {
+    type Error = String;
+
+    fn try_from(raw: RawResponses) -> Result<Self, Self::Error> {
+        let raw = match raw {
+            RawResponses::One(response) => vec![RawResponse::Plain(response)],
+            RawResponses::Many(responses) => responses,
+        };
+        let responses = raw
+            .into_iter()
+            .map(|response| match response {
+                RawResponse::Plain(response) => Ok((Template::parse(&response)?, 1)),
+                RawResponse::Weighted {
+                    weight: 0,
+                    response,
+                } => Err(format!(
+                    "Response \"{}\" has a weight of 0, weights start at 1",
+                    response
+                )),
+                RawResponse::Weighted { response, weight } => {
+                    Ok((Template::parse(&response)?, weight))
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if responses.is_empty() {
+            return Err("responds needs at least one response".to_owned());
+        }
+        Ok(Self {
+            responses,
+            last: None,
+        })
+    }
+}
+
+impl From<Responses> for RawResponses {
+    fn from(responses: Responses) -> Self {
+        if let [(template, 1)] = responses.responses.as_slice() {
+            return RawResponses::One(template.to_string());
+        }
+        RawResponses::Many(
+            responses
+                .responses
+                .into_iter()
+                .map(|(template, weight)| match weight {
+                    1 => RawResponse::Plain(template.into()),
+                    weight => RawResponse::Weighted {
+                        response: template.into(),
+                        weight,
+                    },
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<Template> for Responses {
+    fn from(template: Template) -> Self {
+        Self {
+            responses: vec![(template, 1)],
+            last: None,
+        }
+    }
+}
+
+impl fmt::Display for Responses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let responses = self
+            .responses
+            .iter()
+            .map(|(template, _)| template.to_string())
+            .collect::<Vec<_>>();
+        f.write_str(&responses.join(" | "))
+    }
+}
+
+impl Responses {
+    fn templates(&self) -> impl Iterator<Item = &Template> {
+        self.responses.iter().map(|(template, _)| template)
+    }
+
+    /// Weighted random response, which won't be the last one if avoid_repeats is set.
+    fn choose(&mut self, avoid_repeats: bool) -> &Template {
+        let candidates = (0..self.responses.len())
+            .filter(|&index| {
+                !(avoid_repeats && self.responses.len() > 1 && self.last == Some(index))
+            })
+            .collect::<Vec<_>>();
+        let index = *candidates
+            .choose_weighted(&mut rand::thread_rng(), |&index| self.responses[index].1)
+            .expect("Responses can't be empty");
+        self.last = Some(index);
+        &self.responses[index].0
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleReplyCommand {
     names: Vec<String>,
-    responds: String,
+    responds: Responses,
+    /// With multiple responses, don't pick the same one twice in a row.
+    #[serde(
+        rename = "avoid-repeats",
+        default = "config_default_avoid_repeats",
+        skip_serializing_if = "is_true"
+    )]
+    avoid_repeats: bool,
+    #[serde(flatten)]
+    settings: CommandSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    counter: Option<Counter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<Change>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<Change>,
+    /// For {count}, kept across reloads but not restarts.
+    #[serde(skip)]
+    uses: u64,
+}
+
+/// Maps every alias to its command key, erroring if an alias is used twice.
+fn index(
+    commands: &BTreeMap<String, SimpleReplyCommand>,
+) -> Result<HashMap<String, String>, String> {
+    let mut aliases = HashMap::new();
+    for (key, command) in commands.iter() {
+        for name in command.names.iter() {
+            match aliases.insert(name.to_lowercase(), key.clone()) {
+                Some(other) if &other != key => {
+                    return Err(format!(
+                        "Alias \"{}\" is used by both {} & {}",
+                        name, other, key
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(aliases)
 }
 
+/// Templates can only use counters that exist.
+fn check_counters(
+    commands: &BTreeMap<String, SimpleReplyCommand>,
+    key: &str,
+    template: &Template,
+) -> Result<(), String> {
+    for counter in template.counters() {
+        match counter {
+            None if commands
+                .get(key)
+                .is_none_or(|command| command.counter.is_none()) =>
+            {
+                return Err(format!(
+                    "{{counter}} is used by {}, which isn't a counter",
+                    key
+                ));
+            }
+            Some(counter)
+                if commands
+                    .get(counter)
+                    .is_none_or(|command| command.counter.is_none()) =>
+            {
+                return Err(format!(
+                    "{{counter:{}}} is used by {}, but there is no {} counter",
+                    counter, key, counter
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Commands by key, indexed by alias. Aliases are checked to be unique when loading.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(transparent)]
-pub struct SimpleReplyCommandHandler(HashMap<String, SimpleReplyCommand>);
+#[serde(
+    try_from = "BTreeMap<String, SimpleReplyCommand>",
+    into = "BTreeMap<String, SimpleReplyCommand>"
+)]
+pub struct SimpleReplyCommandHandler {
+    commands: BTreeMap<String, SimpleReplyCommand>,
+    aliases: HashMap<String, String>,
+    /// Set by [`Self::managed`], commands can only be changed from chat if set.
+    data_file: Option<PathBuf>,
+    manage: CustomCommandsConfig,
+    /// Aliases of built-in commands, which can't be taken from chat.
+    reserved: HashSet<String>,
+}
+
+impl TryFrom<BTreeMap<String, SimpleReplyCommand>> for SimpleReplyCommandHandler {
+    type Error = String;
+
+    fn try_from(commands: BTreeMap<String, SimpleReplyCommand>) -> Result<Self, Self::Error> {
+        for (key, command) in commands.iter() {
+            for template in command.responds.templates() {
+                check_counters(&commands, key, template)?;
+            }
+        }
+        Ok(Self {
+            aliases: index(&commands)?,
+            commands,
+            data_file: None,
+            manage: CustomCommandsConfig::default(),
+            reserved: HashSet::new(),
+        })
+    }
+}
+
+impl From<SimpleReplyCommandHandler> for BTreeMap<String, SimpleReplyCommand> {
+    fn from(handler: SimpleReplyCommandHandler) -> Self {
+        handler.commands
+    }
+}
+
+impl SimpleReplyCommandHandler {
+    /// Every command key & its aliases.
+    pub fn commands(&self) -> Vec<(String, Vec<String>)> {
+        self.commands
+            .iter()
+            .map(|(key, command)| (key.clone(), command.names.clone()))
+            .collect()
+    }
+
+    /// Allows changing commands from chat, changes are saved to the config's data file.
+    pub fn managed(mut self, config: &Config) -> Self {
+        self.data_file = Some(config.data_file.clone());
+        self.manage = config.custom_commands.clone();
+        self.reserved = config
+            .builtin_commands()
+            .into_iter()
+            .flat_map(|(_, aliases)| aliases)
+            .map(|alias| alias.to_lowercase())
+            .collect();
+        for key in self.commands.keys() {
+            if let Err(err) = self.write_obs_file(key) {
+                warn!(command = %key, error = %err, "Failed to write OBS file");
+            }
+        }
+        self
+    }
 
-impl Command<String> for SimpleReplyCommandHandler {
-    fn parse_args(&self, chat_message: &MessageData) -> CommandArgsResult<String> {
-        self.0
+    fn render(&mut self, key: &str, args: &[String], chat_message: &MessageData) -> String {
+        let counters = self
+            .commands
             .iter()
-            .find(|(_, command)| {
-                command.names.iter().any(|name| {
-                    chat_message
-                        .message
-                        .text
-                        .to_lowercase()
-                        .starts_with(&format!("!{}", name))
+            .filter_map(|(key, command)| Some((key.clone(), command.counter.as_ref()?.value)))
+            .collect();
+        let command = self.commands.get_mut(key).unwrap();
+        let count = command.uses;
+        let counter = command.counter.as_ref().map(|counter| counter.value);
+        let target = chat_message
+            .message
+            .fragments
+            .iter()
+            .find_map(|fragment| fragment.mention.as_ref())
+            .map_or(&chat_message.chatter.name, |mention| &mention.user_name);
+        command
+            .responds
+            .choose(command.avoid_repeats)
+            .render(&TemplateContext {
+                user: &chat_message.chatter.name,
+                user_login: &chat_message.chatter.login,
+                args,
+                target,
+                channel: &chat_message.broadcaster.name,
+                uptime: runtime::STARTED.elapsed(),
+                count,
+                counter,
+                counters: &counters,
+            })
+    }
+
+    fn write_obs_file(&self, key: &str) -> anyhow::Result<()> {
+        let Some(counter) = &self.commands[key].counter else {
+            return Ok(());
+        };
+        let Some(obs_file) = &counter.obs_file else {
+            return Ok(());
+        };
+        let directory = self
+            .data_file
+            .as_ref()
+            .and_then(|data_file| data_file.parent())
+            .unwrap_or(Path::new(""));
+        persist::write_atomic(&directory.join(obs_file), &counter.value.to_string())
+    }
+
+    /// Key of the command with the alias, the prefix is optional.
+    fn find(&self, command: &ParsedCommand, alias: &str) -> Result<String, ArgError> {
+        let alias = normalize_alias(command, alias);
+        self.aliases
+            .get(&alias)
+            .cloned()
+            .ok_or_else(|| ArgError(format!("Unknown command {}{}", command.prefix(), alias)))
+    }
+
+    /// Errors if any command already uses the alias.
+    fn unused(&self, command: &ParsedCommand, alias: &str) -> Result<String, ArgError> {
+        let alias = normalize_alias(command, alias);
+        if alias.is_empty() || alias.contains(char::is_whitespace) {
+            return Err(ArgError(format!("Invalid command name \"{}\"", alias)));
+        }
+        if self.aliases.contains_key(&alias) || self.reserved.contains(&alias) {
+            return Err(ArgError(format!(
+                "{}{} already exists",
+                command.prefix(),
+                alias
+            )));
+        }
+        Ok(alias)
+    }
+
+    /// Writes one command to the data file, or removes it if none, leaving the rest of the file
+    /// as is.
+    fn save(&self, key: &str, command: Option<&SimpleReplyCommand>) -> anyhow::Result<()> {
+        let Some(data_file) = &self.data_file else {
+            return Ok(());
+        };
+        persist::write_yaml_entry(
+            data_file,
+            "simple_reply_commands",
+            &key.into(),
+            command.map(serde_yml::to_value).transpose()?.as_ref(),
+        )
+    }
+}
+
+fn normalize_alias(command: &ParsedCommand, alias: &str) -> String {
+    alias
+        .strip_prefix(command.prefix())
+        .unwrap_or(alias)
+        .to_lowercase()
+}
+
+impl SimpleReplyCommandHandler {
+    fn parse_template(&self, key: &str, args: &mut ArgReader) -> Result<Template, ArgError> {
+        let template = Template::parse(&args.rest()?).map_err(ArgError)?;
+        check_counters(&self.commands, key, &template).map_err(ArgError)?;
+        Ok(template)
+    }
+
+    /// Errors if other commands would be left using the command's counter once it's deleted.
+    fn deletable(&self, key: String) -> Result<String, ArgError> {
+        let mut remaining = self.commands.clone();
+        remaining.remove(&key);
+        let dependents = remaining
+            .iter()
+            .filter(|(other, command)| {
+                command
+                    .responds
+                    .templates()
+                    .any(|template| check_counters(&remaining, other, template).is_err())
+            })
+            .map(|(other, _)| other.as_str())
+            .collect::<Vec<_>>();
+        if !dependents.is_empty() {
+            return Err(ArgError(format!(
+                "Can't delete {}, its counter is used by {}",
+                key,
+                dependents.join(", ")
+            )));
+        }
+        Ok(key)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SimpleReplyArgs {
+    Reply { key: String, args: Vec<String> },
+    Add { alias: String, responds: Template },
+    Edit { key: String, responds: Template },
+    Delete { key: String },
+    Alias { key: String, alias: String },
+    Info { key: String },
+    Count { key: String, change: CounterChange },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CounterChange {
+    Add(i64),
+    Set(i64),
+}
+
+impl Command<SimpleReplyArgs> for SimpleReplyCommandHandler {
+    fn name(&self) -> &'static str {
+        "simple-reply"
+    }
+
+    fn parse_args(
+        &self,
+        parsed: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<SimpleReplyArgs> {
+        let alias = parsed.name();
+        // Alias without the + or -, so alias permissions & cooldowns are shared with the reply.
+        let count = |key: &String, alias: &str, change: CounterChange| {
+            let command = &self.commands[key];
+            let Some(counter) = &command.counter else {
+                return CommandArgsResult::WrongCommand;
+            };
+            if Permission::of(chat_message) < counter.permission {
+                return CommandArgsResult::UnsufficientPermissions;
+            }
+            CommandArgsResult::from_fn(|| {
+                command.settings.check(alias, chat_message)?;
+                Ok(SimpleReplyArgs::Count {
+                    key: key.clone(),
+                    change,
                 })
             })
-            .map(|(key, _)| CommandArgsResult::Execute(key.clone()))
-            .unwrap_or(CommandArgsResult::WrongCommand)
+        };
+        if let Some(key) = self.aliases.get(alias) {
+            let is_set = parsed
+                .raw_args()
+                .first()
+                .is_some_and(|arg| arg.eq_ignore_ascii_case("set"));
+            if is_set && self.commands[key].counter.is_some() {
+                let mut args = parsed.args(&COUNTER_SET_USAGE);
+                return match args.literal().and_then(|()| args.required()) {
+                    Ok(value) => count(key, alias, CounterChange::Set(value)),
+                    Err(err) => err.into(),
+                };
+            }
+            return CommandArgsResult::from_fn(|| {
+                self.commands[key].settings.check(alias, chat_message)?;
+                Ok(SimpleReplyArgs::Reply {
+                    key: key.clone(),
+                    args: parsed.raw_args().to_vec(),
+                })
+            });
+        }
+        let change = [("+", 1), ("-", -1)]
+            .into_iter()
+            .find_map(|(suffix, change)| {
+                let alias = alias.strip_suffix(suffix)?;
+                Some((self.aliases.get(alias)?, alias, change))
+            });
+        if let Some((key, alias, change)) = change {
+            return count(key, alias, CounterChange::Add(change));
+        }
+        if self.data_file.is_none() {
+            return CommandArgsResult::WrongCommand;
+        }
+        let manage = &self.manage;
+        CommandArgsResult::from_fn(|| {
+            if manage.add.has_alias(ADD_ALIASES, alias) {
+                manage.add.check(alias, chat_message)?;
+                let mut args = parsed.args(&CHANGE_USAGE);
+                let alias = self.unused(parsed, &args.required::<String>()?)?;
+                let responds = self.parse_template(&alias, &mut args)?;
+                Ok(SimpleReplyArgs::Add { alias, responds })
+            } else if manage.edit.has_alias(EDIT_ALIASES, alias) {
+                manage.edit.check(alias, chat_message)?;
+                let mut args = parsed.args(&CHANGE_USAGE);
+                let key = self.find(parsed, &args.required::<String>()?)?;
+                let responds = self.parse_template(&key, &mut args)?;
+                Ok(SimpleReplyArgs::Edit { key, responds })
+            } else if manage.delete.has_alias(DELETE_ALIASES, alias) {
+                manage.delete.check(alias, chat_message)?;
+                let mut args = parsed.args(&COMMAND_USAGE);
+                let key = self.find(parsed, &args.required::<String>()?)?;
+                Ok(SimpleReplyArgs::Delete {
+                    key: self.deletable(key)?,
+                })
+            } else if manage.alias.has_alias(ALIAS_ALIASES, alias) {
+                manage.alias.check(alias, chat_message)?;
+                let mut args = parsed.args(&ALIAS_USAGE);
+                Ok(SimpleReplyArgs::Alias {
+                    key: self.find(parsed, &args.required::<String>()?)?,
+                    alias: self.unused(parsed, &args.required::<String>()?)?,
+                })
+            } else if manage.info.has_alias(INFO_ALIASES, alias) {
+                manage.info.check(alias, chat_message)?;
+                let mut args = parsed.args(&COMMAND_USAGE);
+                Ok(SimpleReplyArgs::Info {
+                    key: self.find(parsed, &args.required::<String>()?)?,
+                })
+            } else {
+                Err(CommandArgsResult::WrongCommand)
+            }
+        })
+    }
+
+    fn help(&self) -> Vec<CommandHelp> {
+        let mut help = self
+            .commands
+            .values()
+            .map(|command| CommandHelp::new(command.names.iter().cloned(), Usage(&[])))
+            .collect::<Vec<_>>();
+        if self.data_file.is_some() {
+            help.extend([
+                CommandHelp::new(self.manage.add.aliases(ADD_ALIASES), CHANGE_USAGE),
+                CommandHelp::new(self.manage.edit.aliases(EDIT_ALIASES), CHANGE_USAGE),
+                CommandHelp::new(self.manage.delete.aliases(DELETE_ALIASES), COMMAND_USAGE),
+                CommandHelp::new(self.manage.alias.aliases(ALIAS_ALIASES), ALIAS_USAGE),
+                CommandHelp::new(self.manage.info.aliases(INFO_ALIASES), COMMAND_USAGE),
+            ]);
+        }
+        help
     }
 
     fn execute(
         &mut self,
-        args: String,
+        args: SimpleReplyArgs,
         chat_message: &twitcheventsub::MessageData,
-        api: &mut twitcheventsub::TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> anyhow::Result<()> {
-        if let Some(command) = self.0.get(&args) {
-            let _ = api.send_chat_message_with_reply(
-                &command.responds,
-                Some(chat_message.message_id.clone()),
-            );
+        // Changes are only made once they're saved, counters reply with their response after.
+        let (key, changed, reply) = match args {
+            SimpleReplyArgs::Reply { key, args } => {
+                let Some(command) = self.commands.get_mut(&key) else {
+                    return Ok(());
+                };
+                command.settings.start_cooldown(chat_message);
+                command.uses += 1;
+                let reply = self.render(&key, &args, chat_message);
+                let _ = api.reply(&reply, &chat_message.message_id);
+                return Ok(());
+            }
+            SimpleReplyArgs::Count { key, change } => {
+                let Some(mut command) = self.commands.get(&key).cloned() else {
+                    return Ok(());
+                };
+                command.settings.start_cooldown(chat_message);
+                let Some(counter) = command.counter.as_mut() else {
+                    return Ok(());
+                };
+                counter.value = match change {
+                    CounterChange::Add(change) => counter.value.saturating_add(change),
+                    CounterChange::Set(value) => value,
+                };
+                (key, Some(command), None)
+            }
+            SimpleReplyArgs::Info { key } => {
+                self.manage.info.start_cooldown(chat_message);
+                let command = &self.commands[&key];
+                let mut info = format!(
+                    "{} ({}) responds \"{}\"",
+                    key,
+                    command.names.join(", "),
+                    command.responds
+                );
+                if let Some(counter) = &command.counter {
+                    info.push_str(&format!(", counter at {}", counter.value));
+                }
+                if let Some(created) = &command.created {
+                    info.push_str(&format!(
+                        ", added by {} on {}",
+                        created.by,
+                        created.at.date_naive()
+                    ));
+                }
+                if let Some(modified) = &command.modified {
+                    info.push_str(&format!(
+                        ", changed by {} on {}",
+                        modified.by,
+                        modified.at.date_naive()
+                    ));
+                }
+                info.push_str(&format!(", used {} times since starting", command.uses));
+                let _ = api.reply(&info, &chat_message.message_id);
+                return Ok(());
+            }
+            SimpleReplyArgs::Add { alias, responds } => {
+                self.manage.add.start_cooldown(chat_message);
+                // Keys of deleted aliases can be left behind, so find one that's free.
+                let key = (1..)
+                    .map(|n| match n {
+                        1 => alias.clone(),
+                        n => format!("{}-{}", alias, n),
+                    })
+                    .find(|key| !self.commands.contains_key(key))
+                    .unwrap();
+                let command = SimpleReplyCommand {
+                    names: vec![alias],
+                    responds: responds.into(),
+                    avoid_repeats: true,
+                    settings: CommandSettings::default(),
+                    counter: None,
+                    created: Some(Change::now(chat_message)),
+                    modified: None,
+                    uses: 0,
+                };
+                let reply = format!("Added {}.", key);
+                (key, Some(command), Some(reply))
+            }
+            SimpleReplyArgs::Edit { key, responds } => {
+                self.manage.edit.start_cooldown(chat_message);
+                let mut command = self.commands[&key].clone();
+                command.responds = responds.into();
+                command.modified = Some(Change::now(chat_message));
+                let reply = format!("Changed {}.", key);
+                (key, Some(command), Some(reply))
+            }
+            SimpleReplyArgs::Delete { key } => {
+                self.manage.delete.start_cooldown(chat_message);
+                let reply = format!("Deleted {}.", key);
+                (key, None, Some(reply))
+            }
+            SimpleReplyArgs::Alias { key, alias } => {
+                self.manage.alias.start_cooldown(chat_message);
+                let mut command = self.commands[&key].clone();
+                command.names.push(alias.clone());
+                command.modified = Some(Change::now(chat_message));
+                let reply = format!("Added alias {} to {}.", alias, key);
+                (key, Some(command), Some(reply))
+            }
+        };
+        info!(
+            chatter.id = %chat_message.chatter.id,
+            chatter.name = %chat_message.chatter.name,
+            command = %key,
+            "Data command changed"
+        );
+        self.save(&key, changed.as_ref())?;
+        match changed {
+            Some(command) => self.commands.insert(key.clone(), command),
+            None => self.commands.remove(&key),
+        };
+        self.aliases = index(&self.commands).map_err(|err| anyhow!(err))?;
+        let reply = match reply {
+            Some(reply) => reply,
+            None => {
+                self.write_obs_file(&key)?;
+                self.render(&key, &[], chat_message)
+            }
+        };
+        let _ = api.reply(&reply, &chat_message.message_id);
+        Ok(())
+    }
+
+    fn reload(&mut self, config: &Config) -> anyhow::Result<()> {
+        let mut commands = config.data().simple_reply_commands.clone();
+        for (key, command) in commands.commands.iter_mut() {
+            if let Some(previous) = self.commands.get(key) {
+                command.settings.inherit_cooldowns(&previous.settings);
+                command.uses = previous.uses;
+            }
         }
+        *self = commands.managed(config);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Responses;
+
+    #[test]
+    fn responses() {
+        let one: Responses = serde_yml::from_str("\"UwU\"").unwrap();
+        assert_eq!(serde_yml::to_string(&one).unwrap().trim(), "UwU");
+
+        let mut many: Responses =
+            serde_yml::from_str("[ \"Maybe\", { response: \"Nope\", weight: 3 } ]").unwrap();
+        assert_eq!(
+            serde_yml::to_string(&many).unwrap().trim(),
+            "- Maybe\n- response: Nope\n  weight: 3"
+        );
+        let mut previous = many.choose(true).to_string();
+        for _ in 0..20 {
+            let response = many.choose(true).to_string();
+            assert_ne!(response, previous);
+            previous = response;
+        }
+
+        let err = serde_yml::from_str::<Responses>("[ { response: \"No\", weight: 0 } ]");
+        assert!(err.unwrap_err().to_string().contains("weights start at 1"));
+        let err = serde_yml::from_str::<Responses>("[]");
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("at least one response"));
+    }
+}
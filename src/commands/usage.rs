@@ -0,0 +1,107 @@
+use std::{cell::RefCell, rc::Rc};
+
+use anyhow::Result;
+use twitcheventsub::MessageData;
+
+use crate::{
+    args::{CommandHelp, Param, ParsedCommand, Usage},
+    chat::{self, ChatClient},
+    command::{Command, CommandArgsResult, CommandSettings},
+    config::Config,
+};
+
+const USAGE_ALIASES: &[&str] = &["usage"];
+const USAGE_USAGE: Usage = Usage(&[Param::optional("command")]);
+
+pub fn config_default_usage() -> CommandSettings {
+    CommandSettings::default()
+}
+
+/// Help of every registered command, kept up to date by the registry.
+pub type SharedHelp = Rc<RefCell<Vec<CommandHelp>>>;
+
+/// Every command & its aliases.
+pub fn commands(settings: &CommandSettings) -> Vec<(String, Vec<String>)> {
+    vec![("usage".to_owned(), settings.aliases(USAGE_ALIASES))]
+}
+
+/// Lists every command, or shows the usage of one.
+pub struct UsageCommand {
+    settings: CommandSettings,
+    prefix: String,
+    help: SharedHelp,
+}
+
+impl UsageCommand {
+    pub fn new(config: &Config, help: SharedHelp) -> Self {
+        Self {
+            settings: config.usage.clone(),
+            prefix: config.prefix.clone(),
+            help,
+        }
+    }
+}
+
+impl Command<Option<String>> for UsageCommand {
+    fn name(&self) -> &'static str {
+        "usage"
+    }
+
+    fn parse_args(
+        &self,
+        command: &ParsedCommand,
+        chat_message: &MessageData,
+    ) -> CommandArgsResult<Option<String>> {
+        let alias = command.name();
+        CommandArgsResult::from_fn(|| {
+            if !self.settings.has_alias(USAGE_ALIASES, alias) {
+                return Err(CommandArgsResult::WrongCommand);
+            }
+            self.settings.check(alias, chat_message)?;
+            Ok(command.args(&USAGE_USAGE).optional()?)
+        })
+    }
+
+    fn help(&self) -> Vec<CommandHelp> {
+        vec![CommandHelp::new(
+            self.settings.aliases(USAGE_ALIASES),
+            USAGE_USAGE,
+        )]
+    }
+
+    fn execute(
+        &mut self,
+        name: Option<String>,
+        chat_message: &MessageData,
+        api: &mut dyn ChatClient,
+    ) -> Result<()> {
+        self.settings.start_cooldown(chat_message);
+        let help = self.help.borrow();
+        let reply = match name {
+            None => {
+                let names = help
+                    .iter()
+                    .filter_map(|help| Some(format!("{}{}", self.prefix, help.aliases.first()?)))
+                    .collect::<Vec<_>>();
+                format!("Commands: {}", names.join(", "))
+            }
+            Some(name) => {
+                let name = name.trim_start_matches(&self.prefix).to_lowercase();
+                match help.iter().find(|help| help.aliases.contains(&name)) {
+                    Some(help) => format!("Usage: {}", help.format(&self.prefix)),
+                    None => format!("Unknown command {}{}", self.prefix, name),
+                }
+            }
+        };
+        let _ = api.reply(&chat::truncate(&reply), &chat_message.message_id);
+        Ok(())
+    }
+
+    fn reload(&mut self, config: &Config) -> Result<()> {
+        let mut settings = config.usage.clone();
+        settings.inherit_cooldowns(&self.settings);
+        self.settings = settings;
+        self.prefix = config.prefix.clone();
+        Ok(())
+    }
+}
@@ -1,39 +1,237 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::commands::{radio::RadioConfig, simple_reply::SimpleReplyCommandHandler};
+#[cfg(feature = "neovim")]
+use crate::commands::neovim::NeovimConfig;
+#[cfg(feature = "radio")]
+use crate::commands::radio::RadioConfig;
+use crate::{
+    announcements::{Announcement, AnnouncementsConfig},
+    args::DEFAULT_PREFIX,
+    command::CommandSettings,
+    commands::{
+        quotes::{QuoteBook, QuotesConfig},
+        simple_reply::{CustomCommandsConfig, SimpleReplyCommandHandler},
+        usage::{self, config_default_usage},
+    },
+    logging::LogConfig,
+    reload::ReloadConfig,
+    supervisor::SupervisorConfig,
+};
+
+fn config_default_prefix() -> String {
+    DEFAULT_PREFIX.to_owned()
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(rename = "bot-user-id")]
     pub bot_user_id: String,
+    /// Prefix for every command.
+    #[serde(default = "config_default_prefix")]
+    pub prefix: String,
+    /// Relative to the config file once loaded.
     #[serde(rename = "data-file")]
     pub data_file: PathBuf,
     #[serde(skip)]
     data: Option<ConfigData>,
-    pub radio: RadioConfig,
+    /// Radio is disabled if not set.
+    #[cfg(feature = "radio")]
+    pub radio: Option<RadioConfig>,
+    /// Kept only to warn that it's configured but not built.
+    #[cfg(not(feature = "radio"))]
+    pub radio: Option<toml::Table>,
+    /// Neovim is disabled if not set.
+    #[cfg(feature = "neovim")]
+    pub neovim: Option<NeovimConfig>,
+    /// Kept only to warn that it's configured but not built.
+    #[cfg(not(feature = "neovim"))]
+    pub neovim: Option<toml::Table>,
+    #[serde(rename = "custom-commands", default)]
+    pub custom_commands: CustomCommandsConfig,
+    #[serde(default = "config_default_usage")]
+    pub usage: CommandSettings,
+    #[serde(default)]
+    pub quotes: QuotesConfig,
+    #[serde(default)]
+    pub announcements: AnnouncementsConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
+    #[serde(default)]
+    pub reload: ReloadConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigData {
     pub simple_reply_commands: SimpleReplyCommandHandler,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub announcements: BTreeMap<String, Announcement>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub quotes: QuoteBook,
 }
 
 impl ConfigData {
-    fn load(path: &PathBuf) -> Result<Self> {
-        Ok(serde_yml::from_str(&std::fs::read_to_string(path)?)?)
+    fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_yml::from_str(&data).with_context(|| format!("Invalid {}", path.display()))
     }
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
-        let mut config: Config = toml::from_str(&std::fs::read_to_string("config.toml")?)?;
+    /// Just the config file, without loading data.
+    fn parse(path: &Path) -> Result<Self> {
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(&config).with_context(|| format!("Invalid {}", path.display()))?;
+        if config.prefix.is_empty() || config.prefix.contains(char::is_whitespace) {
+            return Err(anyhow!(
+                "Invalid {}: prefix must not be empty or contain whitespace",
+                path.display()
+            ));
+        }
+        config.data_file = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&config.data_file);
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with(path, |_| {})
+    }
+
+    /// Like load, with overrides applied before anything is checked.
+    pub fn load_with(path: &Path, overrides: impl FnOnce(&mut Self)) -> Result<Self> {
+        let mut config = Self::parse(path)?;
+        overrides(&mut config);
         config.data = Some(ConfigData::load(&config.data_file)?);
+        config.check_aliases()?;
         Ok(config)
     }
 
+    /// Every command & its aliases, except data commands.
+    pub fn builtin_commands(&self) -> Vec<(String, Vec<String>)> {
+        let mut commands = usage::commands(&self.usage);
+        #[cfg(feature = "radio")]
+        if let Some(radio) = &self.radio {
+            commands.extend(
+                radio
+                    .commands()
+                    .into_iter()
+                    .map(|(name, aliases)| (format!("radio {}", name), aliases)),
+            );
+        }
+        #[cfg(feature = "neovim")]
+        if let Some(neovim) = &self.neovim {
+            commands.extend(
+                neovim
+                    .commands()
+                    .into_iter()
+                    .map(|(name, aliases)| (format!("neovim {}", name), aliases)),
+            );
+        }
+        commands.extend(
+            self.quotes
+                .commands()
+                .into_iter()
+                .map(|(name, aliases)| (format!("quotes {}", name), aliases)),
+        );
+        commands.extend(
+            self.custom_commands
+                .commands()
+                .into_iter()
+                .map(|(name, aliases)| (format!("custom-commands {}", name), aliases)),
+        );
+        commands
+    }
+
+    /// Every command's aliases must be unique, including between built-ins & data commands.
+    fn check_aliases(&self) -> Result<()> {
+        let mut commands = self.builtin_commands();
+        commands.extend(
+            self.data()
+                .simple_reply_commands
+                .commands()
+                .into_iter()
+                .map(|(name, aliases)| (format!("data command {}", name), aliases)),
+        );
+
+        let mut owners: HashMap<String, &str> = HashMap::new();
+        let mut collisions = Vec::new();
+        for (name, aliases) in commands.iter() {
+            for alias in aliases.iter() {
+                match owners.insert(alias.to_lowercase(), name) {
+                    Some(other) if other != name => collisions.push(format!(
+                        "{}{} is used by both {} & {}",
+                        self.prefix, alias, other, name
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        if collisions.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Command alias collisions: {}",
+                collisions.join(", ")
+            ))
+        }
+    }
+
+    /// Unlike load, doesn't stop at the first error, so all files get checked.
+    pub fn check(path: &Path, overrides: impl FnOnce(&mut Self)) -> Vec<anyhow::Error> {
+        let mut errors = Vec::new();
+        let mut config = None;
+        let data_file = match Self::parse(path) {
+            Ok(mut parsed) => {
+                overrides(&mut parsed);
+                let data_file = parsed.data_file.clone();
+                config = Some(parsed);
+                Some(data_file)
+            }
+            Err(err) => {
+                // Still try to find the data file so it gets checked too.
+                let data_file = std::fs::read_to_string(path)
+                    .ok()
+                    .and_then(|config| toml::from_str::<toml::Table>(&config).ok())
+                    .and_then(|config| {
+                        config
+                            .get("data-file")?
+                            .as_str()
+                            .map(|data_file| path.parent().unwrap_or(Path::new("")).join(data_file))
+                    });
+                errors.push(err);
+                data_file
+            }
+        };
+        match data_file {
+            Some(data_file) => match ConfigData::load(&data_file) {
+                Ok(data) => {
+                    // Aliases can only be checked once both files are valid.
+                    if let Some(mut config) = config {
+                        config.data = Some(data);
+                        if let Err(err) = config.check_aliases() {
+                            errors.push(err);
+                        }
+                    }
+                }
+                Err(err) => errors.push(err),
+            },
+            None => errors.push(anyhow!("Could not find data file to check")),
+        }
+        errors
+    }
+
     pub fn data(&self) -> &ConfigData {
         self.data.as_ref().expect("This error should never happen.")
     }
@@ -0,0 +1,91 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+fn config_default_level() -> String {
+    "info".to_owned()
+}
+
+fn config_default_directory() -> PathBuf {
+    PathBuf::from("logs")
+}
+
+fn config_default_max_files() -> usize {
+    7
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// Either a level, or per module filter directives like
+    /// "info,vulae_twitch_bot::commands::radio=debug". RUST_LOG overrides this if set.
+    #[serde(default = "config_default_level")]
+    level: String,
+    #[serde(default = "config_default_directory")]
+    directory: PathBuf,
+    #[serde(default)]
+    rotation: LogRotation,
+    /// Old log files past this are deleted.
+    #[serde(rename = "max-files", default = "config_default_max_files")]
+    max_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: config_default_level(),
+            directory: config_default_directory(),
+            rotation: LogRotation::default(),
+            max_files: config_default_max_files(),
+        }
+    }
+}
+
+/// Logs to both the console & a rotating log file.
+/// Returned guard flushes the log file when dropped, so must be kept alive.
+pub fn initialize(config: &LogConfig) -> Result<WorkerGuard> {
+    let filter =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(&config.level))?;
+
+    let file_appender = RollingFileAppender::builder()
+        .rotation(config.rotation.into())
+        .filename_prefix("vulae-twitch-bot")
+        .filename_suffix("log")
+        .max_log_files(config.max_files)
+        .build(&config.directory)?;
+    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(fmt::layer().with_ansi(false).with_writer(file_writer))
+        .try_init()?;
+
+    Ok(guard)
+}
@@ -1,23 +1,77 @@
+pub mod announcements;
+pub mod args;
+pub mod chat;
+pub mod cli;
 pub mod command;
 pub mod commands;
 pub mod config;
+pub mod logging;
+pub mod permission;
+pub mod persist;
+pub mod reload;
+pub mod runtime;
+pub mod supervisor;
+pub mod template;
 pub mod twitch_event_handler;
 
-use std::time::Duration;
+#[cfg(test)]
+mod replay;
 
-use anyhow::{Error, Result};
+use std::{
+    sync::{mpsc, LazyLock},
+    time::Instant,
+};
+
+use announcements::Announcements;
+use anyhow::{anyhow, Result};
+use chat::{ChatClient, DryRunChatClient};
+use clap::Parser;
+use cli::{Cli, CliCommand};
 use commands::CommandRegistry;
 use config::Config;
+use runtime::{BotEvent, Waker};
+use supervisor::Supervised;
+use tracing::info;
 use twitch_event_handler::TwitchEventHandler;
-use twitcheventsub::{ResponseType, TwitchEventSubApi, TwitchKeys};
+use twitcheventsub::{TwitchEventSubApi, TwitchKeys};
+
+fn check_config(cli: &Cli) -> Result<()> {
+    let errors = Config::check(&cli.config, |config| cli.apply(config));
+    if errors.is_empty() {
+        println!("{} is valid.", cli.config.display());
+        return Ok(());
+    }
+    for err in errors.iter() {
+        eprintln!("{:#}\n", err);
+    }
+    Err(anyhow!("Found {} error(s)", errors.len()))
+}
 
 fn main() -> Result<()> {
-    let config = Config::load()?;
+    let cli = Cli::parse();
+    if let Some(CliCommand::CheckConfig) = cli.command {
+        return check_config(&cli);
+    }
 
-    let keys = TwitchKeys::from_secrets_env().unwrap();
+    LazyLock::force(&runtime::STARTED);
+    let mut config = cli.load_config()?;
+    let _log_guard = logging::initialize(&config.log)?;
 
-    let mut handler_commands = CommandRegistry::initialize(&config)?;
-    let mut handlers: Vec<Box<dyn TwitchEventHandler>> = vec![];
+    // twitcheventsub doesn't export the error type, only Debug is usable.
+    let keys = TwitchKeys::from_secrets_env()
+        .map_err(|err| anyhow!("Missing Twitch keys in .secrets.env: {:?}", err))?;
+
+    let (event_sender, events) = mpsc::channel();
+    let waker = Waker::new(event_sender.clone());
+
+    let handlers: Vec<Box<dyn TwitchEventHandler>> = vec![
+        Box::new(CommandRegistry::initialize(&config, &waker)?),
+        Box::new(Announcements::new(&config)),
+    ];
+    let mut handlers = handlers
+        .into_iter()
+        .map(|handler| Supervised::new(handler.name(), handler, config.supervisor.clone()))
+        .collect::<Vec<_>>();
 
     let api_builder = TwitchEventSubApi::builder(keys)
         .set_redirect_url("http://localhost:3000")
@@ -28,28 +82,62 @@ fn main() -> Result<()> {
 
     // WARNING: twitcheventsub uses a Vec instead of HashSet to keep track of what events are
     // subscribed to. I have no clue if this will break stuff (hopefully not.)
-    let api_builder = api_builder.add_subscriptions(handler_commands.subscribed_events().to_vec());
     let api_builder = handlers.iter().fold(api_builder, |api_builder, handler| {
-        api_builder.add_subscriptions(handler.subscribed_events().to_vec())
+        api_builder.add_subscriptions(handler.inner().subscribed_events().to_vec())
     });
 
-    let mut api = api_builder.build().unwrap();
-    println!("Bot started!");
+    let api = api_builder
+        .build()
+        .map_err(|err| anyhow!("Failed to connect to Twitch: {:?}", err))?;
+    let _watcher = if config.reload.enabled {
+        Some(reload::watch(
+            &[&cli.config, &config.data_file],
+            event_sender.clone(),
+        )?)
+    } else {
+        None
+    };
+    let (twitch_chat, _twitch_thread) = runtime::spawn_twitch_thread(api, event_sender);
+    let mut chat: Box<dyn ChatClient> = if cli.dry_run {
+        Box::new(DryRunChatClient)
+    } else {
+        Box::new(twitch_chat)
+    };
+    info!(dry_run = cli.dry_run, "Bot started!");
+
+    // Handlers get a first update to start up, after that only when woken.
+    waker.wake();
 
+    // Blocks until something happens or a handler's next update is due, nothing is polled.
+    // Handler failures are isolated by their supervisor, so they never end the loop.
     loop {
-        while let Some(response) = api.receive_single_message(Duration::ZERO) {
-            let ResponseType::Event(event) = response else {
-                continue;
-            };
-            handler_commands.handle_event(&event, &mut api)?;
-            handlers.iter_mut().try_for_each(|handler| {
-                handler.handle_event(&event, &mut api)?;
-                Ok::<(), Error>(())
-            })?;
+        let next_update = handlers
+            .iter()
+            .filter(|handler| !handler.is_disabled())
+            .filter_map(|handler| handler.inner().next_update())
+            .min();
+        let event = match next_update {
+            Some(next_update) => {
+                match events.recv_timeout(next_update.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(mpsc::RecvTimeoutError::Timeout) => BotEvent::Wake,
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            None => events.recv()?,
+        };
+        match event {
+            BotEvent::Twitch(event) => handlers.iter_mut().for_each(|handler| {
+                handler.run(chat.as_mut(), |handler, chat| {
+                    handler.handle_event(&event, chat)
+                });
+            }),
+            BotEvent::Wake => handlers.iter_mut().for_each(|handler| {
+                handler.run(chat.as_mut(), |handler, chat| handler.update(chat));
+            }),
+            BotEvent::Reload => {
+                reload::apply(cli.load_config(), &mut config, &mut handlers, chat.as_mut())
+            }
         }
-
-        handler_commands.update(&mut api)?;
-
-        std::thread::sleep(Duration::from_millis(1));
     }
 }
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use twitcheventsub::MessageData;
+
+/// Ordered from least to most privileged.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl Permission {
+    /// Highest permission granted by the chatter's badges.
+    pub fn of(chat_message: &MessageData) -> Self {
+        chat_message
+            .badges
+            .iter()
+            .filter_map(|badge| match badge.set_id.as_str() {
+                "broadcaster" => Some(Permission::Broadcaster),
+                "moderator" | "lead_moderator" => Some(Permission::Moderator),
+                "vip" => Some(Permission::Vip),
+                "subscriber" | "founder" => Some(Permission::Subscriber),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(Permission::Everyone)
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fs::File, io::Write, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+
+/// Writes to a temporary file next to path & renames it over path, so a crash midway never
+/// leaves a half written file behind.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let mut file = File::create(&temporary)
+        .with_context(|| format!("Failed to create {}", temporary.display()))?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// Replaces one entry of a top level mapping in a YAML file, or removes it if value is none.
+/// Only the entry's own lines are rewritten, so comments & formatting elsewhere are kept.
+pub fn write_yaml_entry(
+    path: &Path,
+    section: &str,
+    key: &serde_yml::Value,
+    value: Option<&serde_yml::Value>,
+) -> Result<()> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = replace_yaml_entry(&text, section, key, value)
+        .with_context(|| format!("Failed to update {} in {}", section, path.display()))?;
+    write_atomic(path, &text)
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Not blank or a comment.
+fn is_content(line: &str) -> bool {
+    let line = line.trim_start();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+/// Key of a `key: value` line, & whether the value is on the same line.
+fn line_key(line: &str) -> Option<(serde_yml::Value, bool)> {
+    let line = line.trim_start();
+    let key_end = match line.chars().next()? {
+        quote @ ('"' | '\'') => line[1..].find(quote)? + 2,
+        _ => line
+            .find(": ")
+            .or_else(|| line.ends_with(':').then(|| line.len() - 1))?,
+    };
+    let rest = line[key_end..].strip_prefix(':')?.trim();
+    let key = serde_yml::from_str(&line[..key_end]).ok()?;
+    Some((key, !rest.is_empty() && !rest.starts_with('#')))
+}
+
+fn render_entry(key: &serde_yml::Value, value: &serde_yml::Value, indent: usize) -> Result<String> {
+    let mut entry = serde_yml::Mapping::new();
+    entry.insert(key.clone(), value.clone());
+    Ok(serde_yml::to_string(&entry)?
+        .lines()
+        .map(|line| format!("{}{}", " ".repeat(indent), line))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn replace_yaml_entry(
+    text: &str,
+    section: &str,
+    key: &serde_yml::Value,
+    value: Option<&serde_yml::Value>,
+) -> Result<String> {
+    let mut lines = text.lines().map(str::to_owned).collect::<Vec<_>>();
+    let section_key = serde_yml::Value::from(section);
+    let section_line = lines.iter().position(|line| {
+        is_content(line)
+            && indent(line) == 0
+            && line_key(line).is_some_and(|(key, _)| key == section_key)
+    });
+    match section_line {
+        None => {
+            if let Some(value) = value {
+                lines.push(format!("{}:", section));
+                lines.push(render_entry(key, value, 2)?);
+            }
+        }
+        Some(section_line) => {
+            let section_end = (section_line + 1..lines.len())
+                .find(|&index| is_content(&lines[index]) && indent(&lines[index]) == 0)
+                .unwrap_or(lines.len());
+            let body = section_line + 1..section_end;
+            let last_content = body.clone().rev().find(|&index| is_content(&lines[index]));
+            if line_key(&lines[section_line]).is_some_and(|(_, inline)| inline) {
+                // Like `quotes: {}`, rewritten as a block with the entry changed.
+                let data: serde_yml::Value = serde_yml::from_str(text)?;
+                let mut entries = match data.get(section) {
+                    Some(serde_yml::Value::Mapping(entries)) => entries.clone(),
+                    Some(serde_yml::Value::Null) | None => serde_yml::Mapping::new(),
+                    Some(_) => return Err(anyhow!("{} is not a mapping", section)),
+                };
+                match value {
+                    Some(value) => entries.insert(key.clone(), value.clone()),
+                    None => entries.remove(key),
+                };
+                let mut block = vec![format!("{}:", section)];
+                for (key, value) in entries.iter() {
+                    block.push(render_entry(key, value, 2)?);
+                }
+                let end = last_content.map_or(section_line, |last| last);
+                lines.splice(section_line..=end, block);
+            } else {
+                let child_indent = body
+                    .clone()
+                    .find(|&index| is_content(&lines[index]))
+                    .map_or(2, |index| indent(&lines[index]));
+                let start = body.clone().find(|&index| {
+                    is_content(&lines[index])
+                        && indent(&lines[index]) == child_indent
+                        && line_key(&lines[index]).is_some_and(|(line_key, _)| &line_key == key)
+                });
+                match (start, value) {
+                    (Some(start), value) => {
+                        // Lines indented further belong to the entry, as do `- ` items at the
+                        // same indent. Trailing comments are left for whatever follows.
+                        let mut end = start;
+                        for (index, line) in
+                            lines.iter().enumerate().take(section_end).skip(start + 1)
+                        {
+                            if !is_content(line) {
+                                continue;
+                            }
+                            if indent(line) > child_indent
+                                || (indent(line) == child_indent
+                                    && line.trim_start().starts_with('-'))
+                            {
+                                end = index;
+                            } else {
+                                break;
+                            }
+                        }
+                        let entry = match value {
+                            Some(value) => vec![render_entry(key, value, child_indent)?],
+                            None => vec![],
+                        };
+                        lines.splice(start..=end, entry);
+                    }
+                    (None, Some(value)) => {
+                        let after = last_content.unwrap_or(section_line);
+                        lines.insert(after + 1, render_entry(key, value, child_indent)?);
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+    let mut updated = lines.join("\n");
+    if text.ends_with('\n') || text.is_empty() {
+        updated.push('\n');
+    }
+
+    // Make sure nothing else changed meaning.
+    let data: serde_yml::Value = serde_yml::from_str(&updated)?;
+    let written = data.get(section).and_then(|entries| entries.get(key));
+    if written != value {
+        return Err(anyhow!("Entry was not written as expected"));
+    }
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::replace_yaml_entry;
+
+    const DATA: &str = r#"# Commands
+simple_reply_commands:
+  github:
+    names: [ "github", "gh" ]   # short one too
+    responds: "https://github.com/Vulae"
+
+  "8ball":
+    names: [ "8ball" ]
+    responds:
+    - "Yes."
+    - "No."
+
+# Sent to chat every so often.
+announcements: {}
+"#;
+
+    #[test]
+    fn replaces_only_the_entry() {
+        let value = serde_yml::from_str("{ names: [ ball ], responds: Maybe. }").unwrap();
+        let updated =
+            replace_yaml_entry(DATA, "simple_reply_commands", &"8ball".into(), Some(&value))
+                .unwrap();
+        assert_eq!(
+            updated,
+            DATA.replace(
+                "  \"8ball\":\n    names: [ \"8ball\" ]\n    responds:\n    - \"Yes.\"\n    - \"No.\"\n",
+                "  '8ball':\n    names:\n    - ball\n    responds: Maybe.\n"
+            )
+        );
+
+        let removed =
+            replace_yaml_entry(DATA, "simple_reply_commands", &"github".into(), None).unwrap();
+        assert!(removed.starts_with("# Commands\nsimple_reply_commands:\n\n  \"8ball\":"));
+        assert!(removed.contains("# Sent to chat every so often."));
+    }
+
+    #[test]
+    fn adds_entries() {
+        let value = serde_yml::Value::from("hi");
+        let added =
+            replace_yaml_entry(DATA, "simple_reply_commands", &"new".into(), Some(&value)).unwrap();
+        assert!(added.contains("    - \"No.\"\n  new: hi\n\n# Sent to chat"));
+
+        let added = replace_yaml_entry(DATA, "announcements", &1.into(), Some(&value)).unwrap();
+        assert!(added.ends_with("# Sent to chat every so often.\nannouncements:\n  1: hi\n"));
+
+        let added = replace_yaml_entry(DATA, "quotes", &1.into(), Some(&value)).unwrap();
+        assert!(added.starts_with(DATA));
+        assert!(added.ends_with("quotes:\n  1: hi\n"));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    chat::ChatClient, config::Config, runtime::BotEvent, supervisor::Supervised,
+    twitch_event_handler::TwitchEventHandler,
+};
+
+/// Editors tend to write files in several steps, only reload once they're done.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+fn config_default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadConfig {
+    #[serde(default = "config_default_enabled")]
+    pub enabled: bool,
+    #[serde(rename = "report-in-chat", default)]
+    pub report_in_chat: bool,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: config_default_enabled(),
+            report_in_chat: false,
+        }
+    }
+}
+
+/// Sends [`BotEvent::Reload`] whenever any of the files change.
+/// Parent directories are watched instead of the files, as editors often replace files instead
+/// of writing to them. The watcher stops once the returned value is dropped.
+pub fn watch(files: &[&Path], events: Sender<BotEvent>) -> Result<RecommendedWatcher> {
+    let files = files
+        .iter()
+        .map(|file| Ok(std::path::absolute(file)?))
+        .collect::<Result<Vec<PathBuf>>>()?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    for file in files.iter() {
+        let directory = file
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", file.display()))?;
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+    }
+
+    thread::spawn(move || {
+        while let Ok(event) = rx.recv() {
+            if !is_watched(&files, &event) {
+                continue;
+            }
+            while rx.recv_timeout(DEBOUNCE).is_ok() {}
+            if events.send(BotEvent::Reload).is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+fn is_watched(files: &[PathBuf], event: &notify::Result<notify::Event>) -> bool {
+    match event {
+        Ok(event) => !event.kind.is_access() && event.paths.iter().any(|path| files.contains(path)),
+        Err(err) => {
+            warn!(error = %err, "File watch error");
+            false
+        }
+    }
+}
+
+/// Gives every handler the newly loaded config, or keeps the old one if it failed to load.
+/// Files caught half-written by an editor fail to load here, before any handler sees them, so
+/// they never count as handler failures.
+pub fn apply(
+    loaded: Result<Config>,
+    config: &mut Config,
+    handlers: &mut [Supervised<dyn TwitchEventHandler>],
+    chat: &mut dyn ChatClient,
+) {
+    match loaded {
+        Ok(new_config) => {
+            *config = new_config;
+            handlers.iter_mut().for_each(|handler| {
+                handler.run(chat, |handler, chat| handler.reload(config, chat));
+            });
+            info!("Config reloaded");
+            if config.reload.report_in_chat {
+                let _ = chat.send_message("Config reloaded.");
+            }
+        }
+        Err(err) => {
+            error!(error = %err, "Config reload failed, keeping old config");
+            if config.reload.report_in_chat {
+                let _ = chat.send_message(&format!("Config reload failed: {:#}", err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        path::PathBuf,
+        rc::Rc,
+        sync::mpsc::{self, Receiver},
+        time::{Duration, Instant},
+    };
+
+    use anyhow::Result;
+    use notify::{event::ModifyKind, Event, EventKind};
+
+    use super::{apply, is_watched, watch};
+    use crate::{
+        chat::{ChatClient, RecordingChatClient},
+        config::Config,
+        runtime::BotEvent,
+        supervisor::Supervised,
+        twitch_event_handler::TwitchEventHandler,
+    };
+
+    /// Counts reloads, so it's known whether handlers saw a config.
+    struct Reloads(Rc<Cell<usize>>);
+
+    impl TwitchEventHandler for Reloads {
+        fn name(&self) -> &'static str {
+            "reloads"
+        }
+
+        fn subscribed_events(&self) -> &[twitcheventsub::Subscription] {
+            &[]
+        }
+
+        fn handle_event(
+            &mut self,
+            _event: &twitcheventsub::Event,
+            _api: &mut dyn ChatClient,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn reload(&mut self, config: &Config, _api: &mut dyn ChatClient) -> Result<()> {
+            self.0.set(self.0.get() + 1);
+            // Any data that got through must be complete.
+            anyhow::ensure!(
+                config.data().simple_reply_commands.commands().len() == 2,
+                "Got partial data"
+            );
+            Ok(())
+        }
+    }
+
+    fn recv_before(rx: &Receiver<BotEvent>, deadline: Instant) -> Option<BotEvent> {
+        rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .ok()
+    }
+
+    #[test]
+    fn only_watched_files() {
+        let watched = [PathBuf::from("/config/config.toml")];
+        let event =
+            |path: &str| Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.into()));
+        assert!(is_watched(&watched, &event("/config/config.toml")));
+        assert!(!is_watched(&watched, &event("/config/other.toml")));
+    }
+
+    #[test]
+    fn reloads_on_change() -> Result<()> {
+        let directory =
+            std::env::temp_dir().join(format!("vulae-twitch-bot-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let watched = directory.join("config.toml");
+        std::fs::write(&watched, "")?;
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = watch(&[&watched], tx)?;
+
+        std::fs::write(&watched, "a = 1")?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert!(matches!(recv_before(&rx, deadline), Some(BotEvent::Reload)));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn half_written_data_is_not_a_failure() -> Result<()> {
+        const DATA: &str = "simple_reply_commands:\n  a: { names: [ a ], responds: a }\n  b: { names: [ b ], responds: b }\n";
+        let directory = std::env::temp_dir().join(format!(
+            "vulae-twitch-bot-reload-partial-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory)?;
+        let config_file = directory.join("config.toml");
+        let data_file = directory.join("data.yaml");
+        std::fs::write(
+            &config_file,
+            "bot-user-id = \"1\"\ndata-file = \"data.yaml\"\n[supervisor]\nmax-failures = 1\n",
+        )?;
+        std::fs::write(&data_file, DATA)?;
+        let mut config = Config::load(&config_file)?;
+
+        let reloads = Rc::new(Cell::new(0));
+        let handler: Box<dyn TwitchEventHandler> = Box::new(Reloads(reloads.clone()));
+        let mut handlers = vec![Supervised::new(
+            "reloads",
+            handler,
+            config.supervisor.clone(),
+        )];
+        let mut chat = RecordingChatClient::default();
+        let (tx, rx) = mpsc::channel();
+        let _watcher = watch(&[&config_file, &data_file], tx)?;
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        // Like an editor that has only written part of the file so far.
+        std::fs::write(&data_file, &DATA[..DATA.len() - 10])?;
+        assert!(matches!(recv_before(&rx, deadline), Some(BotEvent::Reload)));
+        apply(
+            Config::load(&config_file),
+            &mut config,
+            &mut handlers,
+            &mut chat,
+        );
+        assert_eq!(reloads.get(), 0);
+        assert!(!handlers[0].is_disabled());
+
+        std::fs::write(&data_file, DATA)?;
+        assert!(matches!(recv_before(&rx, deadline), Some(BotEvent::Reload)));
+        apply(
+            Config::load(&config_file),
+            &mut config,
+            &mut handlers,
+            &mut chat,
+        );
+        assert_eq!(reloads.get(), 1);
+        assert!(!handlers[0].is_disabled());
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
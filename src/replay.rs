@@ -0,0 +1,409 @@
+//! Feeds recorded EventSub events through a handler without connecting to Twitch, and captures
+//! everything the bot would have sent.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use twitcheventsub::Event;
+
+use crate::{
+    chat::{ChatAction, RecordingChatClient},
+    twitch_event_handler::TwitchEventHandler,
+};
+
+#[derive(Debug)]
+pub struct ReplayStep {
+    pub event: Event,
+    pub actions: Vec<ChatAction>,
+}
+
+/// One JSON encoded event per line, blank lines are ignored.
+pub fn load_events(path: impl AsRef<Path>) -> Result<Vec<Event>> {
+    let path = path.as_ref();
+    std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: Invalid event", path.display(), index + 1))
+        })
+        .collect()
+}
+
+/// Runs handle_event & update for each event, recording the chat actions each one caused.
+pub fn replay(
+    handler: &mut dyn TwitchEventHandler,
+    events: impl IntoIterator<Item = Event>,
+) -> Result<Vec<ReplayStep>> {
+    events
+        .into_iter()
+        .map(|event| {
+            let mut chat = RecordingChatClient::default();
+            handler.handle_event(&event, &mut chat)?;
+            handler.update(&mut chat)?;
+            Ok(ReplayStep {
+                event,
+                actions: chat.actions,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+    use twitcheventsub::Event;
+
+    use super::{load_events, replay, ReplayStep};
+    use crate::{
+        chat::{ChatAction, MAX_MESSAGE_LENGTH},
+        commands::{quotes::Quotes, simple_reply::SimpleReplyCommandHandler, CommandRegistry},
+        config::Config,
+    };
+
+    const SIMPLE_REPLY_COMMANDS: &str = r#"
+        github:
+          names: [ "github", "gh" ]
+          responds: "https://github.com/Vulae"
+        uwu:
+          names: [ "uwu", "owo" ]
+          responds: "UwU"
+        secret:
+          names: [ "secret", "hidden" ]
+          responds: "shh"
+          permission: vip
+          alias-permissions: { Hidden: broadcaster }
+        slow:
+          names: [ "slow" ]
+          responds: "zzz"
+          user-cooldown: 60
+        "#;
+
+    fn load_replay(name: &str) -> Result<Vec<Event>> {
+        load_events(format!(
+            "{}/tests/replays/{}.jsonl",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+    }
+
+    fn replay_file(name: &str) -> Result<Vec<ReplayStep>> {
+        let simple_reply_commands: SimpleReplyCommandHandler =
+            serde_yml::from_str(SIMPLE_REPLY_COMMANDS)?;
+        let mut registry = CommandRegistry::default();
+        registry.register(simple_reply_commands);
+        replay(&mut registry, load_replay(name)?)
+    }
+
+    fn message_id(event: &Event) -> &str {
+        match event {
+            Event::ChatMessage(message) => &message.message_id,
+            event => panic!("Not a chat message: {:?}", event),
+        }
+    }
+
+    fn reply_to(step: &ReplayStep, message: &str) -> Vec<ChatAction> {
+        vec![ChatAction::Reply {
+            message: message.to_owned(),
+            reply_to_message_id: message_id(&step.event).to_owned(),
+        }]
+    }
+
+    #[test]
+    fn simple_replies() -> Result<()> {
+        let steps = replay_file("simple_replies")?;
+        assert_eq!(steps.len(), 6);
+        assert_eq!(
+            steps[0].actions,
+            reply_to(&steps[0], "https://github.com/Vulae")
+        );
+        assert_eq!(
+            steps[1].actions,
+            reply_to(&steps[1], "https://github.com/Vulae")
+        );
+        assert_eq!(steps[2].actions, reply_to(&steps[2], "UwU"));
+        assert_eq!(steps[3].actions, vec![]);
+        // Only whole command names match.
+        assert_eq!(steps[4].actions, vec![]);
+        assert_eq!(
+            steps[5].actions,
+            reply_to(&steps[5], "https://github.com/Vulae")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn alias_collisions() {
+        let commands = serde_yml::from_str::<SimpleReplyCommandHandler>(
+            "a: { names: [ a, x ], responds: a }\nb: { names: [ b, X ], responds: b }",
+        );
+        assert!(commands
+            .unwrap_err()
+            .to_string()
+            .contains("Alias \"X\" is used by both a & b"));
+    }
+
+    #[test]
+    fn permissions() -> Result<()> {
+        const NOT_ALLOWED: &str = "You are not allowed to use this command.";
+        let steps = replay_file("permissions")?;
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[0].actions, reply_to(&steps[0], NOT_ALLOWED));
+        assert_eq!(steps[1].actions, reply_to(&steps[1], "shh"));
+        assert_eq!(steps[2].actions, reply_to(&steps[2], "shh"));
+        assert_eq!(steps[3].actions, reply_to(&steps[3], NOT_ALLOWED));
+        assert_eq!(steps[4].actions, reply_to(&steps[4], "shh"));
+        Ok(())
+    }
+
+    #[test]
+    fn cooldowns() -> Result<()> {
+        let steps = replay_file("cooldowns")?;
+        assert_eq!(steps.len(), 5);
+        assert_eq!(steps[0].actions, reply_to(&steps[0], "zzz"));
+        assert_eq!(
+            steps[1].actions,
+            reply_to(&steps[1], "This command is on cooldown, try again in 60s.")
+        );
+        // Cooldown replies are rate limited.
+        assert_eq!(steps[2].actions, vec![]);
+        // Other chatters have their own cooldown, & moderators bypass them.
+        assert_eq!(steps[3].actions, reply_to(&steps[3], "zzz"));
+        assert_eq!(steps[4].actions, reply_to(&steps[4], "zzz"));
+        Ok(())
+    }
+
+    /// Registry with commands that are saved to a data file in a new temporary directory.
+    fn managed_registry(name: &str, commands: &str) -> Result<(PathBuf, CommandRegistry)> {
+        let directory =
+            std::env::temp_dir().join(format!("vulae-twitch-bot-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        std::fs::write(
+            directory.join("config.toml"),
+            "bot-user-id = \"1\"\ndata-file = \"data.yaml\"\n",
+        )?;
+        std::fs::write(
+            directory.join("data.yaml"),
+            format!("# Edited by hand too\nsimple_reply_commands:{}", commands),
+        )?;
+        let config = Config::load(&directory.join("config.toml"))?;
+        let mut registry = CommandRegistry::default();
+        registry.register(config.data().simple_reply_commands.clone().managed(&config));
+        Ok((directory, registry))
+    }
+
+    #[test]
+    fn custom_commands() -> Result<()> {
+        let (directory, mut registry) = managed_registry("custom-commands", SIMPLE_REPLY_COMMANDS)?;
+
+        let mut events = load_replay("custom_commands")?;
+        let rest = events.split_off(8);
+        let steps = replay(&mut registry, events)?;
+        assert_eq!(steps[0].actions, reply_to(&steps[0], "Added hello."));
+        assert_eq!(steps[1].actions, reply_to(&steps[1], "Hi Vulae_!"));
+        assert_eq!(
+            steps[2].actions,
+            reply_to(&steps[2], "!hello already exists")
+        );
+        // Built-in names are taken too.
+        assert_eq!(
+            steps[3].actions,
+            reply_to(&steps[3], "!usage already exists")
+        );
+        assert_eq!(
+            steps[4].actions,
+            reply_to(&steps[4], "Added alias hi to hello.")
+        );
+        assert_eq!(steps[5].actions, reply_to(&steps[5], "Hi SomeViewer!"));
+        assert_eq!(
+            steps[6].actions,
+            reply_to(&steps[6], "You are not allowed to use this command.")
+        );
+        assert_eq!(steps[7].actions, reply_to(&steps[7], "Changed hello."));
+
+        // Changes are saved, without touching other commands.
+        let saved = Config::load(&directory.join("config.toml"))?;
+        let commands = saved.data().simple_reply_commands.commands();
+        assert!(commands.contains(&(
+            "hello".to_owned(),
+            vec!["hello".to_owned(), "hi".to_owned()]
+        )));
+        assert_eq!(commands.len(), 5);
+        let data = std::fs::read_to_string(directory.join("data.yaml"))?;
+        assert!(data.contains("responds: Hey {target}"));
+        assert!(data.contains("by: vulae_"));
+        assert!(data.contains("by: modperson"));
+        assert!(data.contains("alias-permissions:"));
+        assert!(data.starts_with("# Edited by hand too\n"));
+
+        let steps = replay(&mut registry, rest)?;
+        let [ChatAction::Reply { message, .. }] = steps[0].actions.as_slice() else {
+            panic!("Expected a reply: {:?}", steps[0].actions);
+        };
+        assert!(
+            message.starts_with("hello (hello, hi) responds \"Hey {target}\", added by vulae_ on ")
+        );
+        assert!(message.ends_with("used 2 times since starting"));
+        assert_eq!(steps[1].actions, reply_to(&steps[1], "Deleted hello."));
+        assert_eq!(steps[2].actions, vec![]);
+        let saved = Config::load(&directory.join("config.toml"))?;
+        assert_eq!(saved.data().simple_reply_commands.commands().len(), 4);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn counters() -> Result<()> {
+        const COUNTERS: &str = r#"
+          bugs:
+            names: [ "bugs" ]
+            responds: "Bugs fixed: {counter}"
+            counter: { value: 3, obs-file: "bugs.txt" }
+          status:
+            names: [ "status" ]
+            responds: "{counter:bugs} bugs so far"
+          deaths:
+            names: [ "deaths", "d" ]
+            responds: "Deaths: {counter}"
+            user-cooldown: 60
+            alias-permissions: { d: moderator }
+            counter: { permission: everyone }
+          "#;
+        let (directory, mut registry) = managed_registry("counters", COUNTERS)?;
+        assert_eq!(std::fs::read_to_string(directory.join("bugs.txt"))?, "3");
+
+        let steps = replay(&mut registry, load_replay("counters")?)?;
+        assert_eq!(steps[0].actions, reply_to(&steps[0], "Bugs fixed: 3"));
+        assert_eq!(
+            steps[1].actions,
+            reply_to(&steps[1], "You are not allowed to use this command.")
+        );
+        assert_eq!(steps[2].actions, reply_to(&steps[2], "Bugs fixed: 4"));
+        assert_eq!(steps[3].actions, reply_to(&steps[3], "Bugs fixed: 3"));
+        assert_eq!(steps[4].actions, reply_to(&steps[4], "Bugs fixed: 10"));
+        assert_eq!(
+            steps[5].actions,
+            reply_to(
+                &steps[5],
+                "Invalid <value>: expected a whole number. Usage: !bugs set <value>"
+            )
+        );
+        assert_eq!(steps[6].actions, reply_to(&steps[6], "10 bugs so far"));
+        assert_eq!(
+            steps[7].actions,
+            reply_to(
+                &steps[7],
+                "Can't delete bugs, its counter is used by status"
+            )
+        );
+        // Changing a counter goes through the same cooldowns & alias permissions as its reply.
+        assert_eq!(steps[8].actions, reply_to(&steps[8], "Deaths: 1"));
+        assert_eq!(
+            steps[9].actions,
+            reply_to(&steps[9], "This command is on cooldown, try again in 60s.")
+        );
+        assert_eq!(steps[10].actions, reply_to(&steps[10], "Deaths: 2"));
+        assert_eq!(
+            steps[11].actions,
+            reply_to(&steps[11], "You are not allowed to use this command.")
+        );
+
+        assert_eq!(std::fs::read_to_string(directory.join("bugs.txt"))?, "10");
+        let data = std::fs::read_to_string(directory.join("data.yaml"))?;
+        assert!(data.contains("value: 10"));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_counters() {
+        let commands = serde_yml::from_str::<SimpleReplyCommandHandler>(
+            "a: { names: [ a ], responds: \"{counter:b}\" }\nb: { names: [ b ], responds: b }",
+        );
+        assert!(commands
+            .unwrap_err()
+            .to_string()
+            .contains("{counter:b} is used by a, but there is no b counter"));
+    }
+
+    #[test]
+    fn quotes() -> Result<()> {
+        let (directory, mut registry) = managed_registry("quotes", " {}")?;
+        let config = Config::load(&directory.join("config.toml"))?;
+        registry.register(Quotes::new(&config));
+
+        let steps = replay(&mut registry, load_replay("quotes")?)?;
+        let today = chrono::Utc::now().date_naive();
+        assert_eq!(
+            steps[0].actions,
+            reply_to(&steps[0], "There are no quotes yet.")
+        );
+        assert_eq!(
+            steps[1].actions,
+            reply_to(&steps[1], "You are not allowed to use this command.")
+        );
+        assert_eq!(steps[2].actions, reply_to(&steps[2], "Added quote #1."));
+        assert_eq!(steps[3].actions, reply_to(&steps[3], "Added quote #2."));
+        assert_eq!(
+            steps[4].actions,
+            reply_to(
+                &steps[4],
+                &format!("#1: \"I never said that\" - someviewer, {}", today)
+            )
+        );
+        assert_eq!(
+            steps[5].actions,
+            reply_to(
+                &steps[5],
+                &format!("#2: \"chat is very nice\" - vulae_, {}", today)
+            )
+        );
+        assert_eq!(
+            steps[6].actions,
+            reply_to(&steps[6], "2 quotes found: #1, #2")
+        );
+        assert_eq!(steps[7].actions, reply_to(&steps[7], "Deleted quote #1."));
+        assert_eq!(
+            steps[8].actions,
+            reply_to(&steps[8], "Quote #1 doesn't exist.")
+        );
+        // Numbers aren't reused, & long quotes are cut to fit in chat.
+        assert_eq!(steps[9].actions, reply_to(&steps[9], "Added quote #3."));
+        let [ChatAction::Reply { message, .. }] = steps[10].actions.as_slice() else {
+            panic!("Expected a reply: {:?}", steps[10].actions);
+        };
+        assert_eq!(message.chars().count(), MAX_MESSAGE_LENGTH);
+        assert!(message.starts_with("#3: \"la la") && message.ends_with('…'));
+        // Not even the highest number.
+        assert_eq!(steps[11].actions, reply_to(&steps[11], "Deleted quote #3."));
+        assert_eq!(steps[12].actions, reply_to(&steps[12], "Added quote #4."));
+        let [ChatAction::Reply { message, .. }] = steps[13].actions.as_slice() else {
+            panic!("Expected a reply: {:?}", steps[13].actions);
+        };
+        assert_eq!(message.chars().count(), MAX_MESSAGE_LENGTH);
+        assert!(message.starts_with("No quotes contain \"missing missing"));
+
+        let saved = Config::load(&directory.join("config.toml"))?;
+        assert_eq!(
+            saved
+                .data()
+                .quotes
+                .iter()
+                .filter(|(_, quote)| !quote.is_deleted())
+                .map(|(number, _)| *number)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        let data = std::fs::read_to_string(directory.join("data.yaml"))?;
+        assert!(data.contains("said-by: vulae_"));
+        assert!(data.contains("by: modperson"));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+}
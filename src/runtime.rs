@@ -0,0 +1,117 @@
+use std::{
+    sync::{
+        mpsc::{self, Sender},
+        LazyLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tracing::{error, warn};
+use twitcheventsub::{ResponseType, TwitchEventSubApi};
+
+use crate::chat::{ChatAction, ChatClient};
+
+/// How long the Twitch thread waits for an incoming event before sending queued chat actions.
+/// twitcheventsub only exposes a blocking receive with timeout, so outgoing messages may be
+/// delayed by up to this much.
+const TWITCH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Forced at startup, Twitch doesn't tell us when the stream started so this is used instead.
+pub static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+#[derive(Debug)]
+pub enum BotEvent {
+    Twitch(Box<twitcheventsub::Event>),
+    /// Something happened that handlers need to update for.
+    Wake,
+    /// Config or data file changed.
+    Reload,
+}
+
+/// Wakes the main loop up to call update on every handler.
+#[derive(Debug, Clone)]
+pub struct Waker(Sender<BotEvent>);
+
+impl Waker {
+    pub fn new(sender: Sender<BotEvent>) -> Self {
+        Self(sender)
+    }
+
+    pub fn wake(&self) {
+        let _ = self.0.send(BotEvent::Wake);
+    }
+}
+
+/// Chat client that hands actions over to the Twitch thread, which owns the API.
+pub struct TwitchChatClient {
+    actions: Sender<ChatAction>,
+}
+
+impl TwitchChatClient {
+    fn queue(&mut self, action: ChatAction) -> Result<()> {
+        self.actions.send(action)?;
+        Ok(())
+    }
+}
+
+impl ChatClient for TwitchChatClient {
+    fn send_message(&mut self, message: &str) -> Result<()> {
+        self.queue(ChatAction::Message(message.to_owned()))
+    }
+
+    fn reply(&mut self, message: &str, reply_to_message_id: &str) -> Result<()> {
+        self.queue(ChatAction::Reply {
+            message: message.to_owned(),
+            reply_to_message_id: reply_to_message_id.to_owned(),
+        })
+    }
+
+    fn delete_message(&mut self, message_id: &str) -> Result<()> {
+        self.queue(ChatAction::DeleteMessage(message_id.to_owned()))
+    }
+
+    fn timeout_user(&mut self, user_id: &str, duration: u32, reason: &str) -> Result<()> {
+        self.queue(ChatAction::TimeoutUser {
+            user_id: user_id.to_owned(),
+            duration,
+            reason: reason.to_owned(),
+        })
+    }
+}
+
+/// Moves the API to its own thread, forwarding received events to `events`.
+pub fn spawn_twitch_thread(
+    mut api: TwitchEventSubApi,
+    events: Sender<BotEvent>,
+) -> (TwitchChatClient, JoinHandle<()>) {
+    let (actions, rx) = mpsc::channel::<ChatAction>();
+    let handle = thread::spawn(move || loop {
+        match api.receive_single_message(TWITCH_POLL_INTERVAL) {
+            Some(ResponseType::Event(event)) => {
+                let sent = events.send(BotEvent::Twitch(Box::new(event)));
+                // Main loop is gone, nothing left to do.
+                if sent.is_err() {
+                    return;
+                }
+            }
+            Some(ResponseType::Error(err)) => error!(error = ?err, "Twitch error"),
+            Some(ResponseType::Close) => warn!("Twitch connection closed"),
+            _ => {}
+        }
+
+        loop {
+            match rx.try_recv() {
+                Ok(action) => {
+                    if let Err(err) = action.perform(&mut api) {
+                        error!(error = %err, ?action, "Failed to perform chat action");
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            }
+        }
+    });
+    (TwitchChatClient { actions }, handle)
+}
@@ -0,0 +1,206 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::chat::ChatClient;
+
+fn config_default_max_failures() -> usize {
+    3
+}
+
+fn config_default_failure_window() -> u64 {
+    60
+}
+
+fn config_default_disable_duration() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisorConfig {
+    /// Failures within failure-window before a module gets disabled.
+    #[serde(rename = "max-failures", default = "config_default_max_failures")]
+    max_failures: usize,
+    /// Seconds
+    #[serde(rename = "failure-window", default = "config_default_failure_window")]
+    failure_window: u64,
+    /// Seconds
+    #[serde(
+        rename = "disable-duration",
+        default = "config_default_disable_duration"
+    )]
+    disable_duration: u64,
+    #[serde(rename = "report-in-chat", default)]
+    report_in_chat: bool,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: config_default_max_failures(),
+            failure_window: config_default_failure_window(),
+            disable_duration: config_default_disable_duration(),
+            report_in_chat: false,
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Unknown panic"
+    }
+}
+
+/// Isolates errors & panics of a module, disabling it for a while if it keeps failing.
+pub struct Supervised<T: ?Sized> {
+    name: String,
+    inner: Box<T>,
+    config: SupervisorConfig,
+    failures: VecDeque<Instant>,
+    disabled_until: Option<Instant>,
+}
+
+impl<T: ?Sized> Supervised<T> {
+    pub fn new(name: impl Into<String>, inner: Box<T>, config: SupervisorConfig) -> Self {
+        Self {
+            name: name.into(),
+            inner,
+            config,
+            failures: VecDeque::new(),
+            disabled_until: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_until
+            .is_some_and(|disabled_until| Instant::now() < disabled_until)
+    }
+
+    /// Returns none if the module is disabled, or if it failed.
+    pub fn run<R>(
+        &mut self,
+        api: &mut dyn ChatClient,
+        f: impl FnOnce(&mut T, &mut dyn ChatClient) -> Result<R>,
+    ) -> Option<R> {
+        if self.is_disabled() {
+            return None;
+        }
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(inner, api))) {
+            Ok(Ok(result)) => Some(result),
+            Ok(Err(err)) => {
+                self.failed(&format!("{:#}", err), api);
+                None
+            }
+            Err(panic) => {
+                self.failed(&format!("panicked: {}", panic_message(&*panic)), api);
+                None
+            }
+        }
+    }
+
+    fn failed(&mut self, reason: &str, api: &mut dyn ChatClient) {
+        warn!(module = %self.name, reason, "Module failed");
+        if self.config.report_in_chat {
+            let _ = api.send_message(&format!("{} failed: {}", self.name, reason));
+        }
+
+        let now = Instant::now();
+        let failure_window = Duration::from_secs(self.config.failure_window);
+        self.failures
+            .retain(|failure| now.duration_since(*failure) < failure_window);
+        self.failures.push_back(now);
+        if self.failures.len() >= self.config.max_failures {
+            self.failures.clear();
+            self.disabled_until = Some(now + Duration::from_secs(self.config.disable_duration));
+            let message = format!(
+                "{} failed {} times within {}s, disabled for {}s.",
+                self.name,
+                self.config.max_failures,
+                self.config.failure_window,
+                self.config.disable_duration
+            );
+            error!(
+                module = %self.name,
+                disable_duration = self.config.disable_duration,
+                "Module failed too many times, disabling"
+            );
+            if self.config.report_in_chat {
+                let _ = api.send_message(&message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+
+    use super::{Supervised, SupervisorConfig};
+    use crate::chat::{ChatAction, RecordingChatClient};
+
+    #[test]
+    fn disables_after_repeated_failures() {
+        let mut chat = RecordingChatClient::default();
+        let config = SupervisorConfig {
+            report_in_chat: true,
+            ..Default::default()
+        };
+        let mut supervised = Supervised::new("counter", Box::new(0), config);
+
+        assert_eq!(
+            supervised.run(&mut chat, |count, _| {
+                *count += 1;
+                Ok(*count)
+            }),
+            Some(1)
+        );
+        assert_eq!(
+            supervised.run(&mut chat, |_, _| Err::<(), _>(anyhow!("oops"))),
+            None
+        );
+        assert_eq!(
+            supervised.run(&mut chat, |_, _| -> anyhow::Result<()> { panic!("oh no") }),
+            None
+        );
+        assert!(!supervised.is_disabled());
+        assert_eq!(
+            supervised.run(&mut chat, |_, _| Err::<(), _>(anyhow!("oops"))),
+            None
+        );
+        assert!(supervised.is_disabled());
+        // Disabled modules aren't run at all.
+        assert_eq!(supervised.run(&mut chat, |count, _| Ok(*count)), None);
+
+        assert_eq!(
+            chat.actions,
+            vec![
+                ChatAction::Message("counter failed: oops".to_owned()),
+                ChatAction::Message("counter failed: panicked: oh no".to_owned()),
+                ChatAction::Message("counter failed: oops".to_owned()),
+                ChatAction::Message(
+                    "counter failed 3 times within 60s, disabled for 300s.".to_owned()
+                ),
+            ]
+        );
+    }
+}
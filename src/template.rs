@@ -0,0 +1,257 @@
+//! Reply templates, like `Hi {user}, uptime is {uptime}`.
+//! Templates are parsed when loaded, so mistakes are found before they're used.
+
+use std::{collections::HashMap, fmt, time::Duration};
+
+use rand::prelude::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Variable {
+    User,
+    UserLogin,
+    Args,
+    /// Starts at 1.
+    Arg(usize),
+    Target,
+    Channel,
+    Uptime,
+    Count,
+    /// The command's own counter if none.
+    Counter(Option<String>),
+    Random(Vec<String>),
+}
+
+impl Variable {
+    fn parse(variable: &str) -> Result<Self, String> {
+        if let Some(choices) = variable.strip_prefix("random:") {
+            let choices = choices.split('|').map(str::to_owned).collect::<Vec<_>>();
+            if choices.iter().all(String::is_empty) {
+                return Err("{random:...} needs at least one choice".to_owned());
+            }
+            return Ok(Variable::Random(choices));
+        }
+        if let Some(counter) = variable.strip_prefix("counter:") {
+            return Ok(Variable::Counter(Some(counter.to_owned())));
+        }
+        match variable {
+            "user" => Ok(Variable::User),
+            "user.login" => Ok(Variable::UserLogin),
+            "args" => Ok(Variable::Args),
+            "target" => Ok(Variable::Target),
+            "channel" => Ok(Variable::Channel),
+            "uptime" => Ok(Variable::Uptime),
+            "count" => Ok(Variable::Count),
+            "counter" => Ok(Variable::Counter(None)),
+            _ => match variable.strip_prefix("arg").map(str::parse) {
+                Some(Ok(0)) | Some(Err(_)) => Err(format!(
+                    "Unknown variable {{{}}}, arguments start at {{arg1}}",
+                    variable
+                )),
+                Some(Ok(index)) => Ok(Variable::Arg(index)),
+                None => Err(format!("Unknown variable {{{}}}", variable)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(Variable),
+}
+
+/// Everything a template can refer to.
+#[derive(Debug, Clone)]
+pub struct TemplateContext<'a> {
+    /// Display name of the chatter.
+    pub user: &'a str,
+    pub user_login: &'a str,
+    pub args: &'a [String],
+    /// First mentioned user, or the chatter.
+    pub target: &'a str,
+    pub channel: &'a str,
+    pub uptime: Duration,
+    /// Times the command has been used, including this one.
+    pub count: u64,
+    /// Value of the command's own counter.
+    pub counter: Option<i64>,
+    /// Every counter's value, by command key.
+    pub counters: &'a HashMap<String, i64>,
+}
+
+/// `{variable}` is replaced when rendered, `{{` & `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let error = |message: String| format!("Invalid template \"{}\": {}", source, message);
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let Some((variable, rest)) = chars
+                        .as_str()
+                        .split_once('}')
+                        .filter(|(variable, _)| !variable.contains('{'))
+                    else {
+                        return Err(error("Unclosed {".to_owned()));
+                    };
+                    let variable = Variable::parse(variable).map_err(error)?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Variable(variable));
+                    chars = rest.chars();
+                }
+                '}' => return Err(error("Unmatched }, use }} for a literal }".to_owned())),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self {
+            source: source.to_owned(),
+            segments,
+        })
+    }
+
+    /// Every counter used, none being the command's own counter.
+    pub fn counters(&self) -> impl Iterator<Item = Option<&str>> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Variable(Variable::Counter(key)) => Some(key.as_deref()),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, context: &TemplateContext) -> String {
+        let mut rendered = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(variable) => match variable {
+                    Variable::User => rendered.push_str(context.user),
+                    Variable::UserLogin => rendered.push_str(context.user_login),
+                    Variable::Args => rendered.push_str(&context.args.join(" ")),
+                    Variable::Arg(index) => {
+                        rendered.push_str(context.args.get(index - 1).map_or("", String::as_str))
+                    }
+                    Variable::Target => rendered.push_str(context.target),
+                    Variable::Channel => rendered.push_str(context.channel),
+                    Variable::Uptime => rendered.push_str(&format_duration(context.uptime)),
+                    Variable::Count => rendered.push_str(&context.count.to_string()),
+                    Variable::Counter(None) => {
+                        rendered.push_str(&context.counter.unwrap_or_default().to_string())
+                    }
+                    Variable::Counter(Some(key)) => rendered.push_str(
+                        &context
+                            .counters
+                            .get(key)
+                            .copied()
+                            .unwrap_or_default()
+                            .to_string(),
+                    ),
+                    Variable::Random(choices) => {
+                        rendered.push_str(choices.choose(&mut rand::thread_rng()).unwrap())
+                    }
+                },
+            }
+        }
+        rendered
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Template::parse(&source)
+    }
+}
+
+impl From<Template> for String {
+    fn from(template: Template) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Like 2h 5m, or 30s if under a minute.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, minutes) => format!("{}m", minutes),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::{Template, TemplateContext};
+
+    #[test]
+    fn renders_variables() {
+        let args = ["a".to_owned(), "b".to_owned()];
+        let context = TemplateContext {
+            user: "Vulae_",
+            user_login: "vulae_",
+            args: &args,
+            target: "SomeViewer",
+            channel: "Vulae_",
+            uptime: Duration::from_secs(3720),
+            count: 3,
+            counter: Some(-2),
+            counters: &HashMap::from([("bugs".to_owned(), 7)]),
+        };
+        let render = |source: &str| Template::parse(source).unwrap().render(&context);
+        assert_eq!(render("{user} ({user.login})"), "Vulae_ (vulae_)");
+        assert_eq!(render("{args}, {arg2}, {arg3}"), "a b, b, ");
+        assert_eq!(
+            render("hi {target} from {channel}"),
+            "hi SomeViewer from Vulae_"
+        );
+        assert_eq!(render("{uptime} #{count}"), "1h 2m #3");
+        assert_eq!(render("{counter}, {counter:bugs}"), "-2, 7");
+        assert_eq!(render("{{literal}} {random:x}"), "{literal} x");
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        for (source, error) in [
+            ("hi {user", "Unclosed {"),
+            ("{user {target}", "Unclosed {"),
+            ("hi }", "Unmatched }"),
+            ("{username}", "Unknown variable {username}"),
+            ("{arg0}", "arguments start at {arg1}"),
+            ("{random:}", "needs at least one choice"),
+        ] {
+            let err = Template::parse(source).unwrap_err();
+            assert!(err.contains(error), "{}: {}", source, err);
+        }
+    }
+}
@@ -1,10 +1,29 @@
+use std::time::Instant;
+
 use anyhow::Result;
 
+use crate::{chat::ChatClient, config::Config};
+
 pub trait TwitchEventHandler {
+    /// Used for logging & error reporting.
+    fn name(&self) -> &'static str;
     fn subscribed_events(&self) -> &[twitcheventsub::Subscription];
     fn handle_event(
         &mut self,
         event: &twitcheventsub::Event,
-        api: &mut twitcheventsub::TwitchEventSubApi,
+        api: &mut dyn ChatClient,
     ) -> Result<()>;
+    #[allow(unused)]
+    fn update(&mut self, api: &mut dyn ChatClient) -> Result<()> {
+        Ok(())
+    }
+    /// When update should be called next without being woken, none to wait until woken.
+    fn next_update(&self) -> Option<Instant> {
+        None
+    }
+    /// Called with the new config whenever it was successfully reloaded.
+    #[allow(unused)]
+    fn reload(&mut self, config: &Config, api: &mut dyn ChatClient) -> Result<()> {
+        Ok(())
+    }
 }